@@ -0,0 +1,278 @@
+// --- Buffered PostgreSQL Backend ---
+//
+// For edge deployments with intermittent connectivity. Records are written to a local
+// SQLite file, and a background thread forwards them to PostgreSQL whenever it can be
+// reached, marking each one as forwarded once PostgreSQL has it. Queries and cleanup
+// use the local file, which also holds what has not been forwarded yet.
+//
+// A record's `forwarded` column is 0 until PostgreSQL has it and 1 after. A record
+// PostgreSQL rejects on its own, e.g. for a NUL byte, is set to 2 and never sent again,
+// so it cannot hold up the ones behind it.
+
+use crate::sqlite::{self, SqliteBackend};
+use crate::{DatabaseBackend, DatabaseOptions, LongtraceError, PostgresBackend, Record, RecordCursor, RecordQuery, SpanPercentiles};
+use rusqlite::params;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+const UNFORWARDED: &str = "SELECT span_id, parent_id, type, timestamp, message, attr, severity, linked_span_id, status, duration_ns, timestamp_ns, signature, id \
+                           FROM records WHERE forwarded = 0 ORDER BY id LIMIT ?1";
+
+const FORWARDED: i64 = 1;
+const REJECTED: i64 = 2;
+
+type Connect = Box<dyn Fn() -> Result<PostgresBackend, LongtraceError> + Send + Sync>;
+
+pub struct BufferedPostgresBackend {
+    forwarder: Arc<Forwarder>,
+}
+
+struct Forwarder {
+    local: SqliteBackend,
+    connect: Connect,
+    // Connected by the first round that reaches the server
+    postgres: Mutex<Option<PostgresBackend>>,
+    batch_size: usize,
+    // Whether the last round failed, so an outage is logged once
+    failing: AtomicBool,
+}
+
+impl BufferedPostgresBackend {
+    /// Opens the buffer at `local_db` and forwards it every `options.forward_interval`.
+    /// PostgreSQL is only connected by the forwarding, so it may be down at startup.
+    pub fn open(connection_string: &str, local_db: &Path, options: &DatabaseOptions) -> Result<Self, LongtraceError> {
+        let connection_string = connection_string.to_string();
+        // The next round is the retry
+        let postgres_options = DatabaseOptions { connect_retry_attempts: 1, ..options.clone() };
+        Self::with_connect(local_db, options, Box::new(move || PostgresBackend::connect(&connection_string, &postgres_options)))
+    }
+
+    pub(crate) fn with_connect(local_db: &Path, options: &DatabaseOptions, connect: Connect) -> Result<Self, LongtraceError> {
+        if options.forward_interval.is_zero() {
+            return Err(LongtraceError::invalid_config("forward_interval must be positive"));
+        }
+        let local = SqliteBackend::open(local_db, options)?;
+        let conn = local.pool.get()?;
+        sqlite::add_column(&conn, "forwarded", "INTEGER NOT NULL DEFAULT 0")?;
+        conn.execute_batch("CREATE INDEX IF NOT EXISTS idx_records_unforwarded ON records(id) WHERE forwarded = 0")
+            .map_err(LongtraceError::Sqlite)?;
+        drop(conn);
+
+        let forwarder = Arc::new(Forwarder {
+            local,
+            connect,
+            postgres: Mutex::new(None),
+            batch_size: options.batch_size.unwrap_or(1024),
+            failing: AtomicBool::new(false),
+        });
+        let weak = Arc::downgrade(&forwarder);
+        let interval = options.forward_interval;
+        thread::Builder::new().name("longtrace-forwarder".to_string()).spawn(move || Forwarder::run(weak, interval))?;
+        Ok(BufferedPostgresBackend { forwarder })
+    }
+
+    /// Forwards every record not yet in PostgreSQL, oldest first and `batch_size` at a
+    /// time, and returns how many were forwarded. The background thread does this
+    /// every `forward_interval`.
+    pub fn forward_pending(&self) -> Result<u64, LongtraceError> {
+        self.forwarder.forward_pending()
+    }
+
+    /// Records in the local file that have not reached PostgreSQL yet.
+    pub fn unforwarded_count(&self) -> Result<u64, LongtraceError> {
+        self.forwarder.count(0)
+    }
+
+    /// Records in the local file that PostgreSQL rejected, which are not forwarded.
+    pub fn rejected_count(&self) -> Result<u64, LongtraceError> {
+        self.forwarder.count(REJECTED)
+    }
+}
+
+impl Forwarder {
+    fn run(forwarder: Weak<Forwarder>, interval: Duration) {
+        loop {
+            thread::sleep(interval);
+            // Ends once the backend is dropped
+            let Some(forwarder) = forwarder.upgrade() else { break };
+            match forwarder.forward_pending() {
+                Ok(_) => {
+                    if forwarder.failing.swap(false, Ordering::Relaxed) {
+                        eprintln!("Forwarding to PostgreSQL resumed");
+                    }
+                }
+                Err(e) => {
+                    if !forwarder.failing.swap(true, Ordering::Relaxed) {
+                        eprintln!("Forwarding to PostgreSQL failed, retrying every {:?}: {}", interval, e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn forward_pending(&self) -> Result<u64, LongtraceError> {
+        // Held throughout, so a call from outside cannot forward the same records again
+        let mut postgres = self.postgres.lock().unwrap();
+        if postgres.is_none() {
+            *postgres = Some((self.connect)()?);
+        }
+        let postgres = postgres.as_ref().unwrap();
+
+        let mut forwarded = 0;
+        loop {
+            let (ids, records): (Vec<i64>, Vec<Record>) = self.unforwarded()?.into_iter().unzip();
+            if records.is_empty() {
+                return Ok(forwarded);
+            }
+            let batch_len = records.len();
+            let mut left_over = records.clone();
+            let result = postgres.execute_batch(&mut left_over);
+
+            // What PostgreSQL did not take is left in order
+            let mut left_over = left_over.iter().peekable();
+            let mut written = Vec::with_capacity(ids.len());
+            let mut failed = Vec::new();
+            for (id, record) in ids.into_iter().zip(records) {
+                if left_over.peek() == Some(&&record) {
+                    left_over.next();
+                    failed.push((id, record));
+                } else {
+                    written.push(id);
+                }
+            }
+            // Tried on their own, the records that fail because of their values are set
+            // aside; the others stay unforwarded until PostgreSQL takes them
+            let mut rejected = Vec::new();
+            let result = result.or_else(|_| {
+                for (id, record) in failed {
+                    match postgres.execute_batch(&mut vec![record]) {
+                        Ok(()) => written.push(id),
+                        Err(LongtraceError::Query(e)) if PostgresBackend::rejects_record(&e) => rejected.push(id),
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(())
+            });
+            // A crash before this forwards the batch again on the next start
+            self.mark(&written, FORWARDED)?;
+            self.mark(&rejected, REJECTED)?;
+            if !rejected.is_empty() {
+                eprintln!("PostgreSQL rejected {} buffered records, which will not be forwarded", rejected.len());
+            }
+            forwarded += written.len() as u64;
+            result?;
+            if batch_len < self.batch_size {
+                return Ok(forwarded);
+            }
+        }
+    }
+
+    fn unforwarded(&self) -> Result<Vec<(i64, Record)>, LongtraceError> {
+        let conn = self.local.pool.get()?;
+        let mut statement = conn.prepare_cached(UNFORWARDED).map_err(LongtraceError::Sqlite)?;
+        let rows = statement
+            .query_map([self.batch_size as i64], |row| Ok((row.get(12)?, sqlite::record_from_row(row)?)))
+            .map_err(LongtraceError::Sqlite)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LongtraceError::Sqlite)?;
+        Ok(rows)
+    }
+
+    /// Sets `forwarded` to `state` for the records `ids`.
+    fn mark(&self, ids: &[i64], state: i64) -> Result<(), LongtraceError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.local.pool.get()?;
+        let tx = conn.transaction().map_err(LongtraceError::Sqlite)?;
+        {
+            let mut update = tx.prepare_cached("UPDATE records SET forwarded = ?1 WHERE id = ?2").map_err(LongtraceError::Sqlite)?;
+            for id in ids {
+                update.execute([state, *id]).map_err(LongtraceError::Sqlite)?;
+            }
+        }
+        tx.commit().map_err(LongtraceError::Sqlite)
+    }
+
+    fn count(&self, state: i64) -> Result<u64, LongtraceError> {
+        let conn = self.local.pool.get()?;
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM records WHERE forwarded = ?1", [state], |row| row.get(0))
+            .map_err(LongtraceError::Sqlite)?;
+        Ok(count as u64)
+    }
+}
+
+impl DatabaseBackend for BufferedPostgresBackend {
+    fn execute_batch(&self, batch: &mut Vec<Record>) -> Result<(), LongtraceError> {
+        self.forwarder.local.execute_batch(batch)
+    }
+
+    fn query_by_span(&self, span_id: Uuid, limit: Option<i64>) -> Result<Vec<Record>, LongtraceError> {
+        self.forwarder.local.query_by_span(span_id, limit)
+    }
+
+    fn query_by_time_range(
+        &self,
+        start: chrono::NaiveDateTime,
+        end: chrono::NaiveDateTime,
+        record_type: Option<i32>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Record>, LongtraceError> {
+        self.forwarder.local.query_by_time_range(start, end, record_type, limit)
+    }
+
+    fn query_records(&self, query: &RecordQuery) -> Result<Vec<Record>, LongtraceError> {
+        self.forwarder.local.query_records(query)
+    }
+
+    fn query_records_after(&self, query: &RecordQuery, after: Option<RecordCursor>) -> Result<Vec<(RecordCursor, Record)>, LongtraceError> {
+        self.forwarder.local.query_records_after(query, after)
+    }
+
+    fn get_trace_tree(&self, root_parent_id: Uuid) -> Result<Vec<Record>, LongtraceError> {
+        self.forwarder.local.get_trace_tree(root_parent_id)
+    }
+
+    fn query_children(&self, parent_id: Uuid, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.forwarder.local.query_children(parent_id, limit)
+    }
+
+    fn query_slow_spans(&self, min_duration_ns: i64, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.forwarder.local.query_slow_spans(min_duration_ns, limit)
+    }
+
+    fn span_percentiles(&self, message_prefix: &str) -> Result<SpanPercentiles, LongtraceError> {
+        self.forwarder.local.span_percentiles(message_prefix)
+    }
+
+    fn search_by_message(&self, pattern: &str, use_regex: bool, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.forwarder.local.search_by_message(pattern, use_regex, limit)
+    }
+
+    fn search_fulltext(&self, query: &str, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.forwarder.local.search_fulltext(query, limit)
+    }
+
+    /// Trims the local file only; unforwarded records are kept however old, or they
+    /// would never reach PostgreSQL.
+    fn cleanup_older_than(&self, cutoff: chrono::NaiveDateTime) -> Result<u64, LongtraceError> {
+        let conn = self.forwarder.local.pool.get()?;
+        let deleted = conn
+            .execute("DELETE FROM records WHERE forwarded != 0 AND timestamp < ?1", params![sqlite::format_timestamp(cutoff)])
+            .map_err(LongtraceError::Sqlite)?;
+        Ok(deleted as u64)
+    }
+
+    fn vacuum(&self, analyze: bool) -> Result<(), LongtraceError> {
+        self.forwarder.local.vacuum(analyze)
+    }
+
+    fn pool_state(&self) -> Option<r2d2::State> {
+        self.forwarder.local.pool_state()
+    }
+}
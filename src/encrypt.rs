@@ -0,0 +1,135 @@
+// --- Attribute Encryption ---
+//
+// Encrypts selected top-level `attr` values with AES-256-GCM before they are written,
+// for data that has to stay readable to whoever holds the key. Each value becomes
+// `{"_enc": "<base64 of nonce and ciphertext>"}`; the plaintext is the value's JSON.
+
+use crate::{LongtraceError, Record};
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{Map, Value};
+
+/// Key of the object that replaces an encrypted value.
+pub const ENCRYPTED_KEY: &str = "_enc";
+
+const NONCE_LEN: usize = 12;
+
+/// An AES-256 key; its `Debug` output leaves the key out.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct EncryptionKey(pub [u8; 32]);
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+#[derive(Clone)]
+pub struct AttrEncryption {
+    cipher: Aes256Gcm,
+    keys: Vec<String>,
+}
+
+impl std::fmt::Debug for AttrEncryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AttrEncryption").field("keys", &self.keys).finish_non_exhaustive()
+    }
+}
+
+impl AttrEncryption {
+    /// Encrypts the values of `keys` with `key`.
+    pub fn new(key: &EncryptionKey, keys: Vec<String>) -> Self {
+        AttrEncryption { cipher: Aes256Gcm::new(&key.0.into()), keys }
+    }
+
+    /// Replaces the values of the configured keys in the record's `attr`.
+    pub fn encrypt_record(&self, record: &mut Record) {
+        let Some(Value::Object(mut attr)) = record.attr.as_deref().and_then(|attr| serde_json::from_str(attr).ok()) else {
+            return;
+        };
+        let mut changed = false;
+        for key in &self.keys {
+            if let Some(value) = attr.get_mut(key) {
+                *value = self.encrypt_value(value);
+                changed = true;
+            }
+        }
+        if changed {
+            record.attr = Some(Value::Object(attr).to_string());
+        }
+    }
+
+    fn encrypt_value(&self, value: &Value) -> Value {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        match self.cipher.encrypt(&Nonce::from(nonce), value.to_string().as_bytes()) {
+            Ok(ciphertext) => {
+                let mut sealed = nonce.to_vec();
+                sealed.extend(ciphertext);
+                let mut map = Map::new();
+                map.insert(ENCRYPTED_KEY.to_string(), Value::String(BASE64.encode(sealed)));
+                Value::Object(map)
+            }
+            // Only fails for inputs far beyond any attr; the value must not be stored in the clear
+            Err(_) => Value::String(crate::scrub::REDACTED.to_string()),
+        }
+    }
+
+    /// Returns `attr_json` with every encrypted top-level value replaced by the original.
+    pub fn decrypt_attr(&self, attr_json: &str) -> Result<String, LongtraceError> {
+        let mut attr: Value = serde_json::from_str(attr_json)?;
+        if let Value::Object(map) = &mut attr {
+            for value in map.values_mut() {
+                let Some(sealed) = value.as_object().filter(|o| o.len() == 1).and_then(|o| o.get(ENCRYPTED_KEY)).and_then(Value::as_str) else {
+                    continue;
+                };
+                *value = self.decrypt_value(sealed)?;
+            }
+        }
+        Ok(attr.to_string())
+    }
+
+    fn decrypt_value(&self, sealed: &str) -> Result<Value, LongtraceError> {
+        let sealed = BASE64.decode(sealed).map_err(|e| LongtraceError::Decryption(format!("invalid base64: {}", e)))?;
+        if sealed.len() < NONCE_LEN {
+            return Err(LongtraceError::Decryption("the value is too short".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().unwrap();
+        let plaintext = self
+            .cipher
+            .decrypt(&Nonce::from(nonce), ciphertext)
+            .map_err(|_| LongtraceError::Decryption("wrong key or corrupted value".to_string()))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_encrypt_attr() {
+        let encryption = AttrEncryption::new(&EncryptionKey([7; 32]), vec!["diagnosis".to_string(), "ssn".to_string()]);
+        let attr = json!({"patient": 12, "diagnosis": {"code": "J45", "notes": "mild"}, "ssn": "078-05-1120"});
+        let mut record = Record::builder().message("Visit").attr(attr.clone()).build();
+        encryption.encrypt_record(&mut record);
+
+        let stored = record.attr_value().unwrap();
+        assert_eq!(stored["patient"], 12);
+        assert!(stored["diagnosis"][ENCRYPTED_KEY].is_string());
+        assert!(!record.attr.as_deref().unwrap().contains("078-05-1120"));
+        let decrypted: Value = serde_json::from_str(&encryption.decrypt_attr(record.attr.as_deref().unwrap()).unwrap()).unwrap();
+        assert_eq!(decrypted, attr);
+
+        // A fresh nonce per value
+        let mut again = Record::builder().message("Visit").attr(attr).build();
+        encryption.encrypt_record(&mut again);
+        assert_ne!(again.attr_value().unwrap()["ssn"], stored["ssn"]);
+
+        let other = AttrEncryption::new(&EncryptionKey([8; 32]), Vec::new());
+        assert!(matches!(other.decrypt_attr(record.attr.as_deref().unwrap()), Err(LongtraceError::Decryption(_))));
+    }
+}
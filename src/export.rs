@@ -0,0 +1,155 @@
+// --- Parquet Export ---
+//
+// Writes records to a Parquet file for Pandas, Spark and other analytics tools.
+// UUIDs are hyphenated strings and `attr` is JSON text; timestamps are UTC.
+
+use crate::{LongtraceError, Record};
+use parquet::basic::Compression;
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::ByteArray;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+const SCHEMA: &str = r#"
+    message record {
+        REQUIRED BYTE_ARRAY span_id (UTF8);
+        REQUIRED BYTE_ARRAY parent_id (UTF8);
+        REQUIRED INT32 record_type;
+        REQUIRED INT64 timestamp (TIMESTAMP(MICROS, true));
+        REQUIRED BYTE_ARRAY message (UTF8);
+        OPTIONAL BYTE_ARRAY attr (UTF8);
+        REQUIRED INT32 severity;
+        OPTIONAL BYTE_ARRAY linked_span_id (UTF8);
+        REQUIRED INT32 status;
+        OPTIONAL INT64 duration_ns;
+        OPTIONAL INT64 timestamp_ns;
+        OPTIONAL BYTE_ARRAY signature (UTF8);
+    }
+"#;
+
+const ROW_GROUP_SIZE: usize = 64 * 1024;
+
+/// Writes `records` to a new file at `path`, replacing any existing one.
+pub fn write_parquet(path: &Path, records: &[Record]) -> Result<(), LongtraceError> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, properties)?;
+
+    for chunk in records.chunks(ROW_GROUP_SIZE) {
+        let mut row_group = writer.next_row_group()?;
+        let mut index = 0;
+        while let Some(mut column) = row_group.next_column()? {
+            match (index, column.untyped()) {
+                (0, ColumnWriter::ByteArrayColumnWriter(w)) => {
+                    w.write_batch(&strings(chunk.iter().map(|r| r.span_id.to_string())), None, None)?;
+                }
+                (1, ColumnWriter::ByteArrayColumnWriter(w)) => {
+                    w.write_batch(&strings(chunk.iter().map(|r| r.parent_id.to_string())), None, None)?;
+                }
+                (2, ColumnWriter::Int32ColumnWriter(w)) => {
+                    w.write_batch(&chunk.iter().map(|r| r.record_type).collect::<Vec<_>>(), None, None)?;
+                }
+                (3, ColumnWriter::Int64ColumnWriter(w)) => {
+                    let micros: Vec<i64> = chunk.iter().map(|r| r.timestamp.and_utc().timestamp_micros()).collect();
+                    w.write_batch(&micros, None, None)?;
+                }
+                (4, ColumnWriter::ByteArrayColumnWriter(w)) => {
+                    w.write_batch(&strings(chunk.iter().map(|r| r.message.clone())), None, None)?;
+                }
+                (5, ColumnWriter::ByteArrayColumnWriter(w)) => {
+                    // Only present values are written; the definition levels mark the nulls
+                    let definition_levels: Vec<i16> = chunk.iter().map(|r| r.attr.is_some() as i16).collect();
+                    w.write_batch(&strings(chunk.iter().filter_map(|r| r.attr.clone())), Some(&definition_levels), None)?;
+                }
+                (6, ColumnWriter::Int32ColumnWriter(w)) => {
+                    w.write_batch(&chunk.iter().map(|r| i32::from(r.severity)).collect::<Vec<_>>(), None, None)?;
+                }
+                (7, ColumnWriter::ByteArrayColumnWriter(w)) => {
+                    let definition_levels: Vec<i16> = chunk.iter().map(|r| r.linked_span_id.is_some() as i16).collect();
+                    let linked = chunk.iter().filter_map(|r| r.linked_span_id.map(|id| id.to_string()));
+                    w.write_batch(&strings(linked), Some(&definition_levels), None)?;
+                }
+                (8, ColumnWriter::Int32ColumnWriter(w)) => {
+                    w.write_batch(&chunk.iter().map(|r| i32::from(r.status)).collect::<Vec<_>>(), None, None)?;
+                }
+                (9, ColumnWriter::Int64ColumnWriter(w)) => {
+                    let definition_levels: Vec<i16> = chunk.iter().map(|r| r.duration_ns.is_some() as i16).collect();
+                    let durations: Vec<i64> = chunk.iter().filter_map(|r| r.duration_ns).collect();
+                    w.write_batch(&durations, Some(&definition_levels), None)?;
+                }
+                (10, ColumnWriter::Int64ColumnWriter(w)) => {
+                    let definition_levels: Vec<i16> = chunk.iter().map(|r| r.timestamp_ns.is_some() as i16).collect();
+                    let nanos: Vec<i64> = chunk.iter().filter_map(|r| r.timestamp_ns).collect();
+                    w.write_batch(&nanos, Some(&definition_levels), None)?;
+                }
+                (11, ColumnWriter::ByteArrayColumnWriter(w)) => {
+                    let definition_levels: Vec<i16> = chunk.iter().map(|r| r.signature.is_some() as i16).collect();
+                    w.write_batch(&strings(chunk.iter().filter_map(|r| r.signature.clone())), Some(&definition_levels), None)?;
+                }
+                _ => unreachable!("column {} does not match SCHEMA", index),
+            }
+            column.close()?;
+            index += 1;
+        }
+        row_group.close()?;
+    }
+
+    writer.close()?;
+    Ok(())
+}
+
+fn strings(values: impl Iterator<Item = String>) -> Vec<ByteArray> {
+    values.map(|s| ByteArray::from(s.into_bytes())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::INFO;
+    use chrono::Utc;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_write_parquet() {
+        let span_id = Uuid::now_v7();
+        let timestamp = Utc::now().naive_utc();
+        let records: Vec<Record> = (0..3)
+            .map(|i| Record {
+                span_id,
+                parent_id: Uuid::nil(),
+                record_type: 0,
+                timestamp,
+                message: format!("log {}", i),
+                attr: (i != 1).then(|| format!(r#"{{"i":{}}}"#, i)),
+                severity: INFO,
+                linked_span_id: None,
+                status: 0,
+                duration_ns: None,
+                timestamp_ns: None,
+                signature: None,
+            })
+            .collect();
+
+        let path = std::env::temp_dir().join(format!("longtrace-{}.parquet", Uuid::now_v7()));
+        write_parquet(&path, &records).unwrap();
+
+        let reader = SerializedFileReader::try_from(path.as_path()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 3);
+        let rows: Vec<_> = reader.get_row_iter(None).unwrap().map(|row| row.unwrap()).collect();
+        let columns: Vec<(&String, &Field)> = rows[0].get_column_iter().collect();
+        assert_eq!(columns[0], (&"span_id".to_string(), &Field::Str(span_id.to_string())));
+        assert_eq!(columns[3].1, &Field::TimestampMicros(timestamp.and_utc().timestamp_micros()));
+        assert_eq!(columns[5].1, &Field::Str(r#"{"i":0}"#.to_string()));
+        assert_eq!(rows[1].get_column_iter().nth(5).unwrap().1, &Field::Null);
+        assert_eq!(rows[2].get_column_iter().nth(4).unwrap().1, &Field::Str("log 2".to_string()));
+        assert_eq!(rows[0].get_column_iter().nth(10).unwrap().1, &Field::Null);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+// --- Fan-out Backend ---
+//
+// Writes every batch to several backends, e.g. PostgreSQL for keeping and a JSON
+// Lines file for local debugging. A failing backend does not stop the others.
+// Queries and pool state come from the first backend.
+
+use crate::{DatabaseBackend, LongtraceError, Record, RecordCursor, RecordQuery, SpanPercentiles};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+pub struct FanoutBackend {
+    backends: Vec<Arc<dyn DatabaseBackend>>,
+    // For each backend, the records it has written that another one left in a batch,
+    // so a retry of that batch skips them
+    written: Vec<Mutex<VecDeque<Record>>>,
+    // Beyond this the oldest are forgotten, and written again if they come back
+    max_written: usize,
+}
+
+impl FanoutBackend {
+    /// Fans out to `backends`, remembering up to `max_written` records per backend for
+    /// the retries of batches that only some of them took.
+    pub fn new(backends: Vec<Arc<dyn DatabaseBackend>>, max_written: usize) -> Result<Self, LongtraceError> {
+        if backends.is_empty() {
+            return Err(LongtraceError::invalid_config("a fan-out needs at least one backend".to_string()));
+        }
+        let written = backends.iter().map(|_| Mutex::new(VecDeque::new())).collect();
+        Ok(FanoutBackend { backends, written, max_written })
+    }
+
+    fn primary(&self) -> &dyn DatabaseBackend {
+        self.backends[0].as_ref()
+    }
+}
+
+impl DatabaseBackend for FanoutBackend {
+    /// Gives each backend its own copy of the records in `batch` it has not written yet.
+    /// The records any backend could not write stay in `batch`; when it comes back to be
+    /// retried, only the backends that failed them get them again.
+    fn execute_batch(&self, batch: &mut Vec<Record>) -> Result<(), LongtraceError> {
+        let mut errors = Vec::new();
+        let mut left_over = vec![false; batch.len()];
+        let mut taken = Vec::with_capacity(self.backends.len());
+        for (backend, written) in self.backends.iter().zip(&self.written) {
+            // Taken from `written` here, and put back below if the batch is left over again
+            let mut has = vec![false; batch.len()];
+            let mut written = written.lock().unwrap();
+            if !written.is_empty() {
+                for (index, record) in batch.iter().enumerate() {
+                    if let Some(position) = written.iter().position(|r| r == record) {
+                        written.remove(position);
+                        has[index] = true;
+                    }
+                }
+            }
+            drop(written);
+
+            let indices: Vec<usize> = (0..batch.len()).filter(|&index| !has[index]).collect();
+            let mut copy: Vec<Record> = indices.iter().map(|&index| batch[index].clone()).collect();
+            if !copy.is_empty() {
+                if let Err(e) = backend.execute_batch(&mut copy) {
+                    errors.push(e);
+                }
+            }
+            // What the backend did not take is left in order
+            let mut copy = copy.iter().peekable();
+            for index in indices {
+                if copy.peek() == Some(&&batch[index]) {
+                    copy.next();
+                    left_over[index] = true;
+                } else {
+                    has[index] = true;
+                }
+            }
+            taken.push(has);
+        }
+
+        for (written, has) in self.written.iter().zip(taken) {
+            let mut written = written.lock().unwrap();
+            for index in (0..batch.len()).filter(|&index| left_over[index] && has[index]) {
+                written.push_back(batch[index].clone());
+                if written.len() > self.max_written {
+                    written.pop_front();
+                }
+            }
+        }
+        let mut left_over = left_over.into_iter();
+        batch.retain(|_| left_over.next().unwrap());
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(LongtraceError::Multiple(errors)),
+        }
+    }
+
+    fn query_by_span(&self, span_id: Uuid, limit: Option<i64>) -> Result<Vec<Record>, LongtraceError> {
+        self.primary().query_by_span(span_id, limit)
+    }
+
+    fn query_by_time_range(
+        &self,
+        start: chrono::NaiveDateTime,
+        end: chrono::NaiveDateTime,
+        record_type: Option<i32>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Record>, LongtraceError> {
+        self.primary().query_by_time_range(start, end, record_type, limit)
+    }
+
+    fn query_records(&self, query: &RecordQuery) -> Result<Vec<Record>, LongtraceError> {
+        self.primary().query_records(query)
+    }
+
+    fn query_records_after(&self, query: &RecordQuery, after: Option<RecordCursor>) -> Result<Vec<(RecordCursor, Record)>, LongtraceError> {
+        self.primary().query_records_after(query, after)
+    }
+
+    fn get_trace_tree(&self, root_parent_id: Uuid) -> Result<Vec<Record>, LongtraceError> {
+        self.primary().get_trace_tree(root_parent_id)
+    }
+
+    fn query_children(&self, parent_id: Uuid, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.primary().query_children(parent_id, limit)
+    }
+
+    fn query_slow_spans(&self, min_duration_ns: i64, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.primary().query_slow_spans(min_duration_ns, limit)
+    }
+
+    fn span_percentiles(&self, message_prefix: &str) -> Result<SpanPercentiles, LongtraceError> {
+        self.primary().span_percentiles(message_prefix)
+    }
+
+    fn search_by_message(&self, pattern: &str, use_regex: bool, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.primary().search_by_message(pattern, use_regex, limit)
+    }
+
+    fn search_fulltext(&self, query: &str, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.primary().search_fulltext(query, limit)
+    }
+
+    /// Cleans up every backend that supports it and returns the first one's count.
+    fn cleanup_older_than(&self, cutoff: chrono::NaiveDateTime) -> Result<u64, LongtraceError> {
+        let mut errors = Vec::new();
+        let mut deleted = None;
+        for backend in &self.backends {
+            match backend.cleanup_older_than(cutoff) {
+                Ok(count) => {
+                    deleted.get_or_insert(count);
+                }
+                Err(LongtraceError::Unsupported(_)) => {}
+                Err(e) => errors.push(e),
+            }
+        }
+
+        match errors.len() {
+            0 => Ok(deleted.unwrap_or_default()),
+            1 => Err(errors.remove(0)),
+            _ => Err(LongtraceError::Multiple(errors)),
+        }
+    }
+
+    /// Vacuums every backend that supports it.
+    fn vacuum(&self, analyze: bool) -> Result<(), LongtraceError> {
+        let mut errors: Vec<LongtraceError> = self
+            .backends
+            .iter()
+            .filter_map(|backend| match backend.vacuum(analyze) {
+                Ok(()) | Err(LongtraceError::Unsupported(_)) => None,
+                Err(e) => Some(e),
+            })
+            .collect();
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(LongtraceError::Multiple(errors)),
+        }
+    }
+
+    fn pool_state(&self) -> Option<r2d2::State> {
+        self.primary().pool_state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::FileBackend;
+    use crate::{Backend, DatabaseOptions, INFO};
+
+    fn temp_file_backend() -> (Arc<FileBackend>, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("longtrace-{}.ndjson", Uuid::now_v7()));
+        (Arc::new(FileBackend::open(path.clone(), None, &DatabaseOptions::default()).unwrap()), path)
+    }
+
+    #[test]
+    fn test_fanout_writes_every_backend() {
+        let (first, first_path) = temp_file_backend();
+        let (second, second_path) = temp_file_backend();
+        let fanout = FanoutBackend::new(vec![first.clone(), second.clone()], 100).unwrap();
+
+        let span_id = Uuid::now_v7();
+        let mut batch: Vec<Record> = (0..10).map(|i| Record::builder().span_id(span_id).message(format!("log {}", i)).severity(INFO).build()).collect();
+        fanout.execute_batch(&mut batch).unwrap();
+        assert!(batch.is_empty());
+
+        assert_eq!(first.query_by_span(span_id, None).unwrap().len(), 10);
+        assert_eq!(second.query_by_span(span_id, None).unwrap().len(), 10);
+        assert_eq!(fanout.query_by_span(span_id, Some(3)).unwrap().len(), 3);
+
+        std::fs::remove_file(first_path).unwrap();
+        std::fs::remove_file(second_path).unwrap();
+    }
+
+    #[test]
+    fn test_fanout_connection_string() {
+        let backend = Backend::from_connection_string("fanout://host=localhost user=postgres;file:///tmp/trace.ndjson").unwrap();
+        assert_eq!(
+            backend,
+            Backend::Fanout(vec![
+                Backend::Postgres("host=localhost user=postgres".to_string()),
+                Backend::File { path: "/tmp/trace.ndjson".into(), rotation: None },
+            ])
+        );
+        assert!(matches!(Backend::from_connection_string("fanout://"), Err(LongtraceError::InvalidConfig { .. })));
+        assert!(matches!(FanoutBackend::new(Vec::new(), 100), Err(LongtraceError::InvalidConfig { .. })));
+    }
+}
@@ -0,0 +1,398 @@
+// --- File Backend ---
+//
+// Appends records as newline-delimited JSON, one object per line. Needs no external
+// service, so it is the backend for development and CI. Queries scan the current
+// file and every rotated one.
+
+use crate::{DatabaseBackend, DatabaseOptions, LongtraceError, Record, SpanPercentiles, MAX_TRACE_DEPTH};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// When the current file is renamed to `<file>.YYYYMMDD` and a fresh one started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileRotation {
+    /// On the first write of a new day
+    Daily,
+    /// Before a write would grow the file past this many bytes
+    SizeBytes(u64),
+}
+
+impl FromStr for FileRotation {
+    type Err = LongtraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(FileRotation::Daily),
+            _ => match s.parse() {
+                Ok(bytes) if bytes > 0 => Ok(FileRotation::SizeBytes(bytes)),
+                _ => Err(LongtraceError::invalid_config(format!("Unknown rotation '{}', expected 'daily' or a size in bytes", s))),
+            },
+        }
+    }
+}
+
+pub struct FileBackend {
+    pub path: PathBuf,
+    pub rotation: Option<FileRotation>,
+    current: Mutex<CurrentFile>,
+}
+
+struct CurrentFile {
+    file: File,
+    // The day the file was started, which names it when it is rotated
+    date: NaiveDate,
+    size: u64,
+}
+
+impl FileBackend {
+    pub fn open(path: PathBuf, rotation: Option<FileRotation>, options: &DatabaseOptions) -> Result<Self, LongtraceError> {
+        if options.partitioning.is_some() {
+            return Err(LongtraceError::invalid_config("partitioning is not supported by the file backend".to_string()));
+        }
+        if options.table_prefix.is_some() || options.pg_schema.is_some() {
+            return Err(LongtraceError::invalid_config("table_prefix and pg_schema are not supported by the file backend"));
+        }
+
+        let current = CurrentFile::open(&path)?;
+        Ok(FileBackend { path, rotation, current: Mutex::new(current) })
+    }
+
+    /// `<file>.YYYYMMDD`, with a counter added when a day is rotated more than once.
+    fn rotated_path(&self, date: NaiveDate) -> PathBuf {
+        let base = format!("{}.{}", self.path.display(), date.format("%Y%m%d"));
+        let mut candidate = PathBuf::from(&base);
+        let mut counter = 1;
+        while candidate.exists() {
+            candidate = PathBuf::from(format!("{}.{}", base, counter));
+            counter += 1;
+        }
+        candidate
+    }
+
+    /// Rotated files, oldest first.
+    fn rotated_files(&self) -> Result<Vec<PathBuf>, LongtraceError> {
+        let Some(name) = self.path.file_name().and_then(|n| n.to_str()) else {
+            return Ok(Vec::new());
+        };
+        let prefix = format!("{}.", name);
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let suffix = entry.file_name().to_str().and_then(|n| n.strip_prefix(&prefix)).map(str::to_string);
+            if suffix.is_some_and(|s| s.starts_with(|c: char| c.is_ascii_digit())) {
+                files.push(entry.path());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// Every record in the rotated files and the current one, in timestamp order.
+    fn read_all(&self) -> Result<Vec<Record>, LongtraceError> {
+        let mut records = Vec::new();
+        for path in self.rotated_files()? {
+            records.extend(read_records(&path)?);
+        }
+        records.extend(read_records(&self.path)?);
+        // Stable, so records with equal timestamps stay in write order
+        records.sort_by_key(|r| r.timestamp);
+        Ok(records)
+    }
+}
+
+impl CurrentFile {
+    fn open(path: &Path) -> Result<Self, LongtraceError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let metadata = file.metadata()?;
+        // An existing file continues the day it was last written
+        let date = match metadata.modified() {
+            Ok(modified) if metadata.len() > 0 => DateTime::<Utc>::from(modified).date_naive(),
+            _ => Utc::now().date_naive(),
+        };
+        Ok(CurrentFile { file, date, size: metadata.len() })
+    }
+}
+
+fn read_records(path: &Path) -> Result<Vec<Record>, LongtraceError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut records = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            // Skip lines written by something else, or still being written
+            Err(e) => eprintln!("Skipping line in {}: {}", path.display(), LongtraceError::JsonParse(e)),
+        }
+    }
+    Ok(records)
+}
+
+fn limited(mut records: Vec<Record>, limit: Option<i64>) -> Vec<Record> {
+    if let Some(limit) = limit {
+        records.truncate(limit.max(0) as usize);
+    }
+    records
+}
+
+impl DatabaseBackend for FileBackend {
+    fn execute_batch(&self, batch: &mut Vec<Record>) -> Result<(), LongtraceError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = Vec::new();
+        for record in batch.iter() {
+            serde_json::to_writer(&mut lines, record)?;
+            lines.push(b'\n');
+        }
+
+        let mut current = self.current.lock().unwrap();
+        let today = Utc::now().date_naive();
+        let rotate = match self.rotation {
+            Some(FileRotation::Daily) => current.date != today && current.size > 0,
+            Some(FileRotation::SizeBytes(max)) => current.size > 0 && current.size + lines.len() as u64 > max,
+            None => false,
+        };
+        if rotate {
+            let rotated = self.rotated_path(current.date);
+            fs::rename(&self.path, rotated)?;
+            *current = CurrentFile::open(&self.path)?;
+            current.date = today;
+        }
+
+        // One write per batch, so a failure keeps the whole batch
+        current.file.write_all(&lines)?;
+        current.file.flush()?;
+        current.size += lines.len() as u64;
+
+        batch.clear();
+        Ok(())
+    }
+
+    fn query_by_span(&self, span_id: uuid::Uuid, limit: Option<i64>) -> Result<Vec<Record>, LongtraceError> {
+        let records = self.read_all()?.into_iter().filter(|r| r.span_id == span_id).collect();
+        Ok(limited(records, limit))
+    }
+
+    fn query_by_time_range(
+        &self,
+        start: chrono::NaiveDateTime,
+        end: chrono::NaiveDateTime,
+        record_type: Option<i32>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Record>, LongtraceError> {
+        let records = self
+            .read_all()?
+            .into_iter()
+            .filter(|r| r.timestamp >= start && r.timestamp <= end)
+            .filter(|r| record_type.is_none_or(|t| r.record_type == t))
+            .collect();
+        Ok(limited(records, limit))
+    }
+
+    fn query_slow_spans(&self, min_duration_ns: i64, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        let mut records: Vec<Record> = self
+            .read_all()?
+            .into_iter()
+            .filter(|r| r.record_type == 2 && r.duration_ns.is_some_and(|d| d >= min_duration_ns))
+            .collect();
+        // Stable, so equal durations stay in timestamp order
+        records.sort_by_key(|r| std::cmp::Reverse(r.duration_ns));
+        Ok(limited(records, Some(limit)))
+    }
+
+    fn span_percentiles(&self, message_prefix: &str) -> Result<SpanPercentiles, LongtraceError> {
+        let mut durations: Vec<i64> = self
+            .read_all()?
+            .into_iter()
+            .filter(|r| r.record_type == 2 && r.message.starts_with(message_prefix))
+            .filter_map(|r| r.duration_ns)
+            .collect();
+        durations.sort_unstable();
+        Ok(SpanPercentiles::from_sorted(&durations))
+    }
+
+    fn search_by_message(&self, pattern: &str, use_regex: bool, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        if use_regex {
+            return Err(LongtraceError::Unsupported("regex search is not supported by the file backend"));
+        }
+        let pattern = pattern.to_lowercase();
+        let mut records: Vec<Record> = self.read_all()?.into_iter().filter(|r| r.message.to_lowercase().contains(&pattern)).collect();
+        records.reverse();
+        Ok(limited(records, Some(limit)))
+    }
+
+    fn search_fulltext(&self, _query: &str, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("full-text search is not supported by the file backend"))
+    }
+
+    fn query_children(&self, parent_id: uuid::Uuid, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        let records = self.read_all()?.into_iter().filter(|r| r.parent_id == parent_id).collect();
+        Ok(limited(records, Some(limit)))
+    }
+
+    fn get_trace_tree(&self, root_parent_id: uuid::Uuid) -> Result<Vec<Record>, LongtraceError> {
+        // Walk the tree one level at a time, visiting each span once so cycles terminate
+        let records = self.read_all()?;
+        let mut included = vec![false; records.len()];
+        let mut visited = HashSet::from([root_parent_id]);
+        let mut parents = HashSet::from([root_parent_id]);
+        for _ in 0..MAX_TRACE_DEPTH {
+            let mut children = HashSet::new();
+            for (record, included) in records.iter().zip(included.iter_mut()) {
+                if !*included && parents.contains(&record.parent_id) {
+                    *included = true;
+                    if visited.insert(record.span_id) {
+                        children.insert(record.span_id);
+                    }
+                }
+            }
+            if children.is_empty() {
+                break;
+            }
+            parents = children;
+        }
+
+        Ok(records.into_iter().zip(included).filter(|(_, included)| *included).map(|(r, _)| r).collect())
+    }
+
+    /// Deletes rotated files that only hold records older than `cutoff`; the current
+    /// file is never touched.
+    fn cleanup_older_than(&self, cutoff: chrono::NaiveDateTime) -> Result<u64, LongtraceError> {
+        let mut deleted = 0;
+        for path in self.rotated_files()? {
+            let records = read_records(&path)?;
+            if records.iter().all(|r| r.timestamp < cutoff) {
+                fs::remove_file(&path)?;
+                deleted += records.len() as u64;
+            }
+        }
+        Ok(deleted)
+    }
+
+    fn vacuum(&self, _analyze: bool) -> Result<(), LongtraceError> {
+        // Deleted files have already freed their space
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Backend, RustDatabase, INFO};
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("longtrace-{}", Uuid::now_v7()));
+        fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_file_round_trip() {
+        let dir = temp_dir();
+        let path = dir.join("trace.ndjson");
+        let db = RustDatabase::new(&format!("file://{}", path.display()), DatabaseOptions::default()).expect("Failed to open file backend");
+        assert_eq!(db.db_name, path.display().to_string());
+
+        let root_parent_id = Uuid::now_v7();
+        let span_id = Uuid::now_v7();
+        db.report("Span".to_string(), span_id, root_parent_id, None, 1, INFO).unwrap();
+        let attr = json!({"key": "value"}).to_string();
+        db.report("Child".to_string(), Uuid::now_v7(), span_id, Some(attr), 0, INFO).unwrap();
+        db.report_span_end("Span".to_string(), span_id, root_parent_id, None, 0, Some(std::time::Duration::from_millis(5))).unwrap();
+        db.flush().unwrap();
+
+        // One JSON object per line, with attr embedded rather than quoted
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = contents.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1]["attr"]["key"], "value");
+        assert_eq!(lines[1]["parent_id"], span_id.to_string());
+
+        assert_eq!(db.query_by_span(span_id, None).unwrap().len(), 2);
+        assert_eq!(db.get_slow_spans(5_000_000, 10).unwrap()[0].duration_ns, 5_000_000);
+        assert!(db.get_slow_spans(5_000_001, 10).unwrap().is_empty());
+        let found = db.search_by_message("SPAN", false, 10).unwrap();
+        assert_eq!((found.len(), found[0].record_type), (2, 2));
+        let trace = db.get_trace_tree(root_parent_id).unwrap();
+        assert_eq!(trace.iter().map(|r| r.message.as_str()).collect::<Vec<_>>(), ["Span", "Child", "Span"]);
+        assert_eq!(db.get_children(span_id, true, 10).unwrap()[0].message, "Child");
+        assert_eq!(db.get_children(root_parent_id, true, 10).unwrap().len(), 2);
+
+        drop(db);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_rotation_by_size() {
+        let dir = temp_dir();
+        let path = dir.join("trace.ndjson");
+        let backend = FileBackend::open(path.clone(), Some(FileRotation::SizeBytes(1)), &DatabaseOptions::default()).unwrap();
+
+        let record = |message: &str| Record {
+            span_id: Uuid::now_v7(),
+            parent_id: Uuid::nil(),
+            record_type: 0,
+            timestamp: Utc::now().naive_utc(),
+            message: message.to_string(),
+            attr: None,
+            severity: INFO,
+            linked_span_id: None,
+            status: 0,
+            duration_ns: None,
+            timestamp_ns: None,
+            signature: None,
+        };
+        for message in ["first", "second", "third"] {
+            backend.execute_batch(&mut vec![record(message)]).unwrap();
+        }
+
+        // Every write after the first rotates, and same-day names get a counter
+        let today = Utc::now().format("%Y%m%d").to_string();
+        let rotated = backend.rotated_files().unwrap();
+        let names: Vec<String> = rotated.iter().map(|p| p.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        assert_eq!(names, [format!("trace.ndjson.{}", today), format!("trace.ndjson.{}.1", today)]);
+        assert_eq!(read_records(&path).unwrap()[0].message, "third");
+
+        let start = Utc::now().naive_utc() - chrono::Duration::hours(1);
+        let end = Utc::now().naive_utc();
+        let all = backend.query_by_time_range(start, end, None, None).unwrap();
+        assert_eq!(all.iter().map(|r| r.message.as_str()).collect::<Vec<_>>(), ["first", "second", "third"]);
+
+        assert_eq!(backend.cleanup_older_than(end + chrono::Duration::seconds(1)).unwrap(), 2);
+        assert!(backend.rotated_files().unwrap().is_empty());
+        assert_eq!(backend.query_by_time_range(start, end, None, None).unwrap().len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_file_connection_string() {
+        let backend = Backend::from_connection_string("file:///tmp/trace.ndjson?rotate=daily").unwrap();
+        assert_eq!(backend, Backend::File { path: PathBuf::from("/tmp/trace.ndjson"), rotation: Some(FileRotation::Daily) });
+        let backend = Backend::from_connection_string("file:///tmp/trace.ndjson?rotate=1048576").unwrap();
+        assert_eq!(backend, Backend::File { path: PathBuf::from("/tmp/trace.ndjson"), rotation: Some(FileRotation::SizeBytes(1 << 20)) });
+        assert!(matches!(Backend::from_connection_string("file:///tmp/trace.ndjson?rotate=hourly"), Err(LongtraceError::InvalidConfig { .. })));
+        assert!(matches!(Backend::from_connection_string("file:///tmp/trace.ndjson?compress=1"), Err(LongtraceError::InvalidConfig { .. })));
+    }
+}
@@ -0,0 +1,243 @@
+// --- Jaeger Export ---
+//
+// Writes records in the JSON format Jaeger's query API returns and its UI loads from a
+// file. A span's start and end records make one span, and the events, logs and
+// exceptions reported inside it become its logs. Records outside any exported span
+// are left out.
+
+use crate::{LongtraceError, Record, EVENT, EXCEPTION, LINK, STATUS_ERROR, STATUS_OK};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use uuid::Uuid;
+
+const PROCESS_ID: &str = "p1";
+
+struct JaegerSpan {
+    trace_id: Uuid,
+    start: Record,
+    end: Option<Record>,
+    logs: Vec<Value>,
+    references: Vec<Value>,
+    exception: bool,
+}
+
+/// Writes the spans in `records`, oldest first, to a new file at `path` as Jaeger JSON,
+/// and returns how many spans were written.
+pub fn write_jaeger_json(path: &Path, service_name: &str, records: &[Record]) -> Result<u64, LongtraceError> {
+    let mut spans: Vec<JaegerSpan> = Vec::new();
+    let mut index: HashMap<Uuid, usize> = HashMap::new();
+    for record in records {
+        match record.record_type {
+            1 => {
+                // Like the OTLP exporter: children share their parent's trace, and a
+                // root continues its (possibly remote) parent's trace or starts its own
+                let trace_id = match index.get(&record.parent_id) {
+                    Some(&parent) => spans[parent].trace_id,
+                    None if record.parent_id.is_nil() => record.span_id,
+                    None => record.parent_id,
+                };
+                index.insert(record.span_id, spans.len());
+                spans.push(JaegerSpan {
+                    trace_id,
+                    start: record.clone(),
+                    end: None,
+                    logs: Vec::new(),
+                    references: Vec::new(),
+                    exception: false,
+                });
+            }
+            2 => {
+                if let Some(&span) = index.get(&record.span_id) {
+                    spans[span].end = Some(record.clone());
+                }
+            }
+            LINK => {
+                if let (Some(&span), Some(linked)) = (index.get(&record.span_id), record.linked_span_id) {
+                    let trace_id = index.get(&linked).map_or(linked, |&other| spans[other].trace_id);
+                    spans[span].references.push(reference("FOLLOWS_FROM", trace_id, linked));
+                }
+            }
+            0 | EVENT | EXCEPTION => {
+                if let Some(&span) = index.get(&record.parent_id) {
+                    spans[span].logs.push(log(record));
+                    spans[span].exception |= record.record_type == EXCEPTION;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Jaeger groups spans by trace, each trace in the order its first span started
+    let mut traces: Vec<(Uuid, Vec<Value>)> = Vec::new();
+    for span in &spans {
+        let value = to_span(span, &index, &spans);
+        match traces.iter_mut().find(|(trace_id, _)| *trace_id == span.trace_id) {
+            Some((_, trace_spans)) => trace_spans.push(value),
+            None => traces.push((span.trace_id, vec![value])),
+        }
+    }
+    let data: Vec<Value> = traces
+        .into_iter()
+        .map(|(trace_id, spans)| {
+            json!({
+                "traceID": trace_id.simple().to_string(),
+                "spans": spans,
+                "processes": { PROCESS_ID: { "serviceName": service_name, "tags": [] } },
+                "warnings": null,
+            })
+        })
+        .collect();
+
+    let out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    serde_json::to_writer(out, &json!({ "data": data }))?;
+    Ok(spans.len() as u64)
+}
+
+fn to_span(span: &JaegerSpan, index: &HashMap<Uuid, usize>, spans: &[JaegerSpan]) -> Value {
+    let start = &span.start;
+    let mut references = Vec::new();
+    if !start.parent_id.is_nil() {
+        let trace_id = index.get(&start.parent_id).map_or(span.trace_id, |&parent| spans[parent].trace_id);
+        references.push(reference("CHILD_OF", trace_id, start.parent_id));
+    }
+    references.extend(span.references.iter().cloned());
+
+    let mut tags = tags(start);
+    // A span that was never seen ending has no duration
+    let duration = match &span.end {
+        Some(end) => {
+            // An exception that left the span marks it as failed unless a status was set
+            if end.status == STATUS_ERROR || (span.exception && end.status != STATUS_OK) {
+                tags.push(json!({ "key": "error", "type": "bool", "value": true }));
+            }
+            end.duration_ns.map_or_else(|| unix_micros(end) - unix_micros(start), |nanos| nanos / 1000)
+        }
+        None => 0,
+    };
+
+    json!({
+        "traceID": span.trace_id.simple().to_string(),
+        "spanID": span_id(start.span_id),
+        "operationName": start.message,
+        "references": references,
+        "startTime": unix_micros(start),
+        "duration": duration.max(0),
+        "tags": tags,
+        "logs": span.logs,
+        "processID": PROCESS_ID,
+        "warnings": null,
+    })
+}
+
+/// Jaeger span IDs are 64 bits; the low half of a v7 UUID is the random part.
+fn span_id(id: Uuid) -> String {
+    id.as_bytes()[8..].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn reference(ref_type: &str, trace_id: Uuid, span: Uuid) -> Value {
+    json!({ "refType": ref_type, "traceID": trace_id.simple().to_string(), "spanID": span_id(span) })
+}
+
+// Records without `timestamp_ns` only have microseconds
+fn unix_micros(record: &Record) -> i64 {
+    record.timestamp_ns.map_or_else(|| record.timestamp_utc().timestamp_micros(), |nanos| nanos / 1000)
+}
+
+/// The message as the `event` field, then the top-level `attr` keys.
+fn log(record: &Record) -> Value {
+    let event = if record.record_type == EXCEPTION { "error".to_string() } else { record.message.clone() };
+    let mut fields = vec![json!({ "key": "event", "type": "string", "value": event })];
+    if record.record_type == EXCEPTION {
+        fields.push(json!({ "key": "message", "type": "string", "value": record.message }));
+    }
+    fields.extend(tags(record));
+    json!({ "timestamp": unix_micros(record), "fields": fields })
+}
+
+/// Top-level `attr` keys as typed tags; nested values are kept as JSON text.
+fn tags(record: &Record) -> Vec<Value> {
+    let Some(Value::Object(map)) = record.attr_value() else {
+        return Vec::new();
+    };
+    map.into_iter().map(|(key, value)| tag(key, value)).collect()
+}
+
+fn tag(key: String, value: Value) -> Value {
+    let (kind, value) = match value {
+        Value::Bool(b) => ("bool", Value::Bool(b)),
+        Value::Number(n) if n.is_i64() => ("int64", Value::Number(n)),
+        Value::Number(n) => ("float64", Value::Number(n)),
+        Value::String(s) => ("string", Value::String(s)),
+        other => ("string", Value::String(other.to_string())),
+    };
+    json!({ "key": key, "type": kind, "value": value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks the fields and types Jaeger requires of a trace file.
+    fn assert_jaeger_schema(file: &Value) {
+        let data = file["data"].as_array().expect("data is an array");
+        for trace in data {
+            let trace_id = trace["traceID"].as_str().expect("traceID is a string");
+            assert_eq!(trace_id.len(), 32);
+            assert!(trace["processes"].is_object());
+            for span in trace["spans"].as_array().expect("spans is an array") {
+                assert_eq!(span["traceID"], trace_id);
+                assert_eq!(span["spanID"].as_str().unwrap().len(), 16);
+                assert!(span["operationName"].is_string());
+                assert!(span["startTime"].is_u64() && span["duration"].is_u64());
+                assert!(trace["processes"].get(span["processID"].as_str().unwrap()).is_some());
+                for reference in span["references"].as_array().unwrap() {
+                    assert!(["CHILD_OF", "FOLLOWS_FROM"].contains(&reference["refType"].as_str().unwrap()));
+                    assert!(reference["traceID"].is_string() && reference["spanID"].is_string());
+                }
+                for tag in span["tags"].as_array().unwrap() {
+                    assert!(["string", "bool", "int64", "float64"].contains(&tag["type"].as_str().unwrap()));
+                    assert!(tag["key"].is_string() && !tag["value"].is_null());
+                }
+                for log in span["logs"].as_array().unwrap() {
+                    assert!(log["timestamp"].is_u64() && log["fields"].is_array());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_write_jaeger_json() {
+        let root = Uuid::now_v7();
+        let mut records = vec![Record::builder().span_id(root).record_type(1).message("request").attr(json!({"path": "/", "attempt": 1})).build()];
+        let children: Vec<Uuid> = (0..4).map(|_| Uuid::now_v7()).collect();
+        for (i, &child) in children.iter().enumerate() {
+            records.push(Record::builder().span_id(child).parent_id(root).record_type(1).message(format!("step {}", i)).build());
+            records.push(Record::builder().parent_id(child).record_type(EVENT).message("cache miss").attr(json!({"key": i})).build());
+            records.push(Record::builder().span_id(child).parent_id(root).record_type(2).status(STATUS_OK).duration_ns(2_500).build());
+        }
+        records.push(Record::builder().parent_id(root).record_type(EXCEPTION).message("boom").build());
+        records.push(Record::builder().span_id(root).record_type(2).duration_ns(1_000_000).build());
+        // Not inside an exported span
+        records.push(Record::builder().message("stray").build());
+
+        let path = std::env::temp_dir().join(format!("longtrace-{}.jaeger.json", Uuid::now_v7()));
+        assert_eq!(write_jaeger_json(&path, "checkout", &records).unwrap(), 5);
+        let file: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_jaeger_schema(&file);
+
+        let [trace] = file["data"].as_array().unwrap().as_slice() else { panic!("expected one trace") };
+        assert_eq!(trace["traceID"], root.simple().to_string());
+        assert_eq!(trace["processes"]["p1"]["serviceName"], "checkout");
+        let spans = trace["spans"].as_array().unwrap();
+        assert_eq!(spans.len(), 5);
+        assert_eq!(spans[0]["operationName"], "request");
+        assert_eq!(spans[0]["duration"], 1000);
+        assert!(spans[0]["tags"].as_array().unwrap().contains(&json!({"key": "error", "type": "bool", "value": true})));
+        assert!(spans[0]["tags"].as_array().unwrap().contains(&json!({"key": "attempt", "type": "int64", "value": 1})));
+        assert_eq!(spans[1]["references"], json!([{"refType": "CHILD_OF", "traceID": root.simple().to_string(), "spanID": span_id(root)}]));
+        assert_eq!(spans[1]["duration"], 2);
+        assert_eq!(spans[1]["logs"][0]["fields"], json!([{"key": "event", "type": "string", "value": "cache miss"}, {"key": "key", "type": "int64", "value": 0}]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+// --- Kafka Backend ---
+//
+// Produces every record as a JSON message to a Kafka topic, for pipelines that ingest
+// traces from Kafka rather than reading the database. The span ID is the message key,
+// so the records of one span stay in one partition, in order.
+
+use crate::{DatabaseBackend, LongtraceError, Record, SpanPercentiles};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::ClientConfig;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long a batch may take to be acknowledged before its records count as failed.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct KafkaBackend {
+    pub topic: String,
+    producer: FutureProducer,
+}
+
+impl KafkaBackend {
+    /// `brokers` is a comma-separated `host:port` list; nothing is connected until the
+    /// first batch.
+    pub fn new(brokers: &str, topic: String) -> Result<Self, LongtraceError> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("message.timeout.ms", FLUSH_TIMEOUT.as_millis().to_string())
+            .create()?;
+        Ok(KafkaBackend { topic, producer })
+    }
+}
+
+impl DatabaseBackend for KafkaBackend {
+    fn execute_batch(&self, batch: &mut Vec<Record>) -> Result<(), LongtraceError> {
+        let mut deliveries = Vec::with_capacity(batch.len());
+        for record in batch.iter() {
+            let key = record.span_id.to_string();
+            let payload = serde_json::to_vec(record)?;
+            let message = FutureRecord::to(&self.topic).key(&key).payload(&payload);
+            deliveries.push(self.producer.send_result(message).map_err(|(e, _)| e));
+        }
+        let mut error = self.producer.flush(FLUSH_TIMEOUT).err();
+
+        // Flushed or timed out, so every delivery is settled by now. Called on the
+        // writer's blocking pool, which may block on its runtime
+        let runtime = tokio::runtime::Handle::current();
+        let mut deliveries = deliveries.into_iter();
+        batch.retain(|_| {
+            let failed = match deliveries.next().unwrap() {
+                Ok(delivery) => match runtime.block_on(delivery) {
+                    Ok(Ok(_)) => None,
+                    Ok(Err((e, _))) => Some(e),
+                    // Only when the producer is dropped meanwhile
+                    Err(_) => Some(rdkafka::error::KafkaError::Canceled),
+                },
+                Err(e) => Some(e),
+            };
+            let keep = failed.is_some();
+            error = error.take().or(failed);
+            keep
+        });
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn query_by_span(&self, _span_id: Uuid, _limit: Option<i64>) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Kafka backend"))
+    }
+
+    fn query_by_time_range(
+        &self,
+        _start: chrono::NaiveDateTime,
+        _end: chrono::NaiveDateTime,
+        _record_type: Option<i32>,
+        _limit: Option<i64>,
+    ) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Kafka backend"))
+    }
+
+    fn get_trace_tree(&self, _root_parent_id: Uuid) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Kafka backend"))
+    }
+
+    fn query_children(&self, _parent_id: Uuid, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Kafka backend"))
+    }
+
+    fn query_slow_spans(&self, _min_duration_ns: i64, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Kafka backend"))
+    }
+
+    fn span_percentiles(&self, _message_prefix: &str) -> Result<SpanPercentiles, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Kafka backend"))
+    }
+
+    fn search_by_message(&self, _pattern: &str, _use_regex: bool, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Kafka backend"))
+    }
+
+    fn search_fulltext(&self, _query: &str, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Kafka backend"))
+    }
+
+    fn cleanup_older_than(&self, _cutoff: chrono::NaiveDateTime) -> Result<u64, LongtraceError> {
+        Err(LongtraceError::Unsupported("cleanup is not supported by the Kafka backend"))
+    }
+
+    fn vacuum(&self, _analyze: bool) -> Result<(), LongtraceError> {
+        Err(LongtraceError::Unsupported("vacuum is not supported by the Kafka backend"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Backend, DatabaseOptions, RustDatabase, INFO};
+    use rdkafka::consumer::{BaseConsumer, Consumer};
+    use rdkafka::mocking::MockCluster;
+    use rdkafka::{Message, Offset, TopicPartitionList};
+
+    #[test]
+    fn test_kafka_connection_string() {
+        let backend = Backend::from_connection_string("kafka://broker1:9092,broker2:9092/topic/traces").unwrap();
+        assert_eq!(backend, Backend::Kafka { brokers: "broker1:9092,broker2:9092".to_string(), topic: "traces".to_string() });
+        assert!(matches!(Backend::from_connection_string("kafka://broker1:9092/traces"), Err(LongtraceError::InvalidConfig { .. })));
+        assert!(matches!(Backend::from_connection_string("kafka:///topic/traces"), Err(LongtraceError::InvalidConfig { .. })));
+    }
+
+    #[test]
+    fn test_kafka_backend() {
+        let cluster = MockCluster::new(1).unwrap();
+        cluster.create_topic("traces", 1, 1).unwrap();
+        let backend = Backend::Kafka { brokers: cluster.bootstrap_servers(), topic: "traces".to_string() };
+        let db = RustDatabase::open(backend, DatabaseOptions { capture_metadata: false, ..Default::default() }).unwrap();
+        let span_ids: Vec<Uuid> = (0..10).map(|_| Uuid::now_v7()).collect();
+        for (i, &span_id) in span_ids.iter().enumerate() {
+            db.report(format!("Kafka log {}", i), span_id, Uuid::nil(), None, 0, INFO).unwrap();
+        }
+        db.flush().unwrap();
+        assert_eq!(db.writer_stats().unwrap().total_flushed, 10);
+        assert!(matches!(db.query_by_span(span_ids[0], None), Err(LongtraceError::Unsupported(_))));
+
+        let consumer: BaseConsumer = ClientConfig::new()
+            .set("bootstrap.servers", cluster.bootstrap_servers())
+            .set("group.id", "longtrace-test")
+            .create()
+            .unwrap();
+        let mut partitions = TopicPartitionList::new();
+        partitions.add_partition_offset("traces", 0, Offset::Beginning).unwrap();
+        consumer.assign(&partitions).unwrap();
+        let mut messages = Vec::new();
+        while messages.len() < 10 {
+            let message = consumer.poll(Duration::from_secs(5)).expect("timed out waiting for a message").unwrap();
+            let record: Record = serde_json::from_slice(message.payload().unwrap()).unwrap();
+            messages.push((String::from_utf8(message.key().unwrap().to_vec()).unwrap(), record));
+        }
+        for (i, (key, record)) in messages.iter().enumerate() {
+            assert_eq!(*key, span_ids[i].to_string());
+            assert_eq!(record.span_id, span_ids[i]);
+            assert_eq!(record.message, format!("Kafka log {}", i));
+        }
+    }
+}
@@ -0,0 +1,152 @@
+// --- log Crate Bridge ---
+//
+// Reports the records of the `log` macros through a registry, so Rust code using
+// `log::info!` and friends ends up in the same tables as the Python tracers.
+
+use crate::{Record, DEBUG, DEFAULT_REGISTRY, ERROR, INFO, REGISTRY, WARN};
+use log::{Level, LevelFilter, Log, Metadata, SetLoggerError};
+use std::thread;
+use uuid::Uuid;
+
+impl From<&log::Record<'_>> for Record {
+    /// A log record with a new span ID and the call site in `attr`.
+    fn from(record: &log::Record<'_>) -> Self {
+        let severity = match record.level() {
+            Level::Error => ERROR,
+            Level::Warn => WARN,
+            Level::Info => INFO,
+            Level::Debug | Level::Trace => DEBUG,
+        };
+        let attr = serde_json::json!({
+            "target": record.target(),
+            "module_path": record.module_path(),
+            "file": record.file(),
+            "line": record.line(),
+        });
+        Record::builder()
+            .span_id(Uuid::now_v7())
+            .message(record.args().to_string())
+            .attr(attr)
+            .severity(severity)
+            .build()
+    }
+}
+
+/// A `log::Log` that reports every record to a registry; records are dropped while
+/// the registry is not initialized.
+pub struct LongtraceLogger {
+    registry: String,
+}
+
+impl LongtraceLogger {
+    pub fn new(registry: impl Into<String>) -> Self {
+        LongtraceLogger { registry: registry.into() }
+    }
+}
+
+impl Log for LongtraceLogger {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        // Filtered by `log::max_level`
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        // Logs of the database drivers on the writer threads would otherwise be written
+        // by the same writers, each write logging again
+        if thread::current().name() == Some("longtrace-writer") {
+            return;
+        }
+        let Some(db) = REGISTRY.get(&self.registry).map(|db| db.clone()) else {
+            return;
+        };
+        if let Err(e) = db.report_record(Record::from(record)) {
+            eprintln!("Failed to report log record: {}", e);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(db) = REGISTRY.get(&self.registry).map(|db| db.clone()) {
+            if let Err(e) = db.flush() {
+                eprintln!("Failed to flush log records: {}", e);
+            }
+        }
+    }
+}
+
+/// Installs a `LongtraceLogger` for the default registry as the `log` crate's logger.
+/// Fails if another logger is already installed.
+pub fn install_log_bridge(max_level: LevelFilter) -> Result<(), SetLoggerError> {
+    log::set_boxed_logger(Box::new(LongtraceLogger::new(DEFAULT_REGISTRY)))?;
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DatabaseOptions, RustDatabase};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_log_record_conversion() {
+        let args = format_args!("Disk at {}%", 91);
+        let record = log::Record::builder()
+            .args(args)
+            .level(Level::Warn)
+            .target("storage")
+            .module_path(Some("app::storage"))
+            .file(Some("src/storage.rs"))
+            .line(Some(42))
+            .build();
+        let converted = Record::from(&record);
+        assert_eq!(converted.message, "Disk at 91%");
+        assert_eq!(converted.severity, WARN);
+        assert_eq!((converted.parent_id, converted.record_type), (Uuid::nil(), 0));
+        let attr: serde_json::Value = serde_json::from_str(converted.attr.as_deref().unwrap()).unwrap();
+        assert_eq!(attr["module_path"], "app::storage");
+        assert_eq!(attr["file"], "src/storage.rs");
+        assert_eq!(attr["line"], 42);
+    }
+
+    #[test]
+    fn test_log_bridge() {
+        let dir = std::env::temp_dir().join(format!("longtrace-{}", Uuid::now_v7()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("log.ndjson");
+        let db = RustDatabase::new(&format!("file://{}", path.display()), DatabaseOptions::default()).unwrap();
+        REGISTRY.insert("log-bridge".to_string(), Arc::new(db));
+
+        log::set_boxed_logger(Box::new(LongtraceLogger::new("log-bridge"))).unwrap();
+        log::set_max_level(LevelFilter::Info);
+        log::info!("hello");
+        log::debug!("filtered out");
+        log::logger().flush();
+
+        // Like a notice of the PostgreSQL driver during migrations: the logger looks up
+        // the registry that is being initialized
+        let reopen_path = path.clone();
+        let (done, finished) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let result = crate::register_database("log-bridge", true, || {
+                log::info!("while opening");
+                RustDatabase::new(&format!("file://{}", reopen_path.display()), DatabaseOptions::default())
+            });
+            done.send(result.map(|(_, replaced)| replaced.is_some()).map_err(|e| e.to_string())).unwrap();
+        });
+        let replaced = finished.recv_timeout(std::time::Duration::from_secs(30)).expect("initializing deadlocked with the logger");
+        assert_eq!(replaced, Ok(true));
+        log::logger().flush();
+
+        let (_, db) = REGISTRY.remove("log-bridge").unwrap();
+        // Other tests may log through the bridge while it is installed
+        let records = db.query_by_time_range(chrono::NaiveDateTime::MIN, chrono::NaiveDateTime::MAX, None, None).unwrap();
+        let hello: Vec<_> = records.iter().filter(|r| r.message == "hello").collect();
+        assert_eq!(hello.len(), 1);
+        assert_eq!(hello[0].severity, INFO);
+        assert_eq!(records.iter().filter(|r| r.message == "while opening").count(), 1);
+        assert!(records.iter().all(|r| r.message != "filtered out"));
+
+        drop(db);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
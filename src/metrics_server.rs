@@ -0,0 +1,91 @@
+// --- Prometheus Metrics ---
+//
+// Mirrors the writers' counters into Prometheus metrics and serves them on `/metrics`
+// for scraping. The metrics are process-wide: every database and registry adds to
+// the same counters, as a scrape sees one process.
+
+use crate::LongtraceError;
+use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, Registry, TextEncoder};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Duration;
+
+pub(crate) struct PrometheusMetrics {
+    registry: Registry,
+    records_inserted: IntCounter,
+    records_failed: IntCounter,
+    flush_duration: Histogram,
+    pending_records: IntGauge,
+}
+
+pub(crate) fn metrics() -> &'static PrometheusMetrics {
+    static METRICS: OnceLock<PrometheusMetrics> = OnceLock::new();
+    METRICS.get_or_init(|| {
+        let registry = Registry::new();
+        let metrics = PrometheusMetrics {
+            records_inserted: IntCounter::new("records_inserted_total", "Records written by the writers").unwrap(),
+            records_failed: IntCounter::new("records_failed_total", "Records that failed every retry").unwrap(),
+            flush_duration: Histogram::with_opts(HistogramOpts::new(
+                "batch_flush_duration_seconds",
+                "Time spent writing a batch, including retries",
+            ))
+            .unwrap(),
+            pending_records: IntGauge::new("pending_records", "Records in the writers' unflushed batches").unwrap(),
+            registry,
+        };
+        // The names are fixed and registered once, so this cannot fail
+        metrics.registry.register(Box::new(metrics.records_inserted.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.records_failed.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.flush_duration.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.pending_records.clone())).unwrap();
+        metrics
+    })
+}
+
+impl PrometheusMetrics {
+    pub(crate) fn record_flush(&self, elapsed: Duration) {
+        self.flush_duration.observe(elapsed.as_secs_f64());
+    }
+
+    pub(crate) fn record_written(&self, inserted: u64, failed: u64) {
+        self.records_inserted.inc_by(inserted);
+        self.records_failed.inc_by(failed);
+    }
+
+    /// Moves the gauge by the change of one writer's pending records from `reported`
+    /// to `pending`, and returns `pending` for its next report.
+    pub(crate) fn report_pending(&self, reported: usize, pending: usize) -> usize {
+        self.pending_records.add(pending as i64 - reported as i64);
+        pending
+    }
+}
+
+/// The registry holding the writer metrics of every database in the process.
+pub fn registry() -> Registry {
+    metrics().registry.clone()
+}
+
+/// Serves the metrics in the Prometheus text format on `http://0.0.0.0:<port>/metrics`
+/// from a background thread, and returns the port; with `port` 0 the system picks one.
+pub fn start_metrics_server(port: u16) -> Result<u16, LongtraceError> {
+    let server = tiny_http::Server::http(("0.0.0.0", port)).map_err(|e| LongtraceError::Io(std::io::Error::other(e)))?;
+    let port = server.server_addr().to_ip().map_or(port, |addr| addr.port());
+    thread::Builder::new().name("longtrace-metrics".to_string()).spawn(move || {
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let encoder = TextEncoder::new();
+                let mut body = Vec::new();
+                if let Err(e) = encoder.encode(&registry().gather(), &mut body) {
+                    eprintln!("Encoding the metrics failed: {}", e);
+                }
+                let content_type = tiny_http::Header::from_bytes("Content-Type", encoder.format_type()).unwrap();
+                tiny_http::Response::from_data(body).with_header(content_type)
+            } else {
+                tiny_http::Response::from_data(b"Not Found".to_vec()).with_status_code(404)
+            };
+            // The scraper may have hung up; the next request is unaffected
+            let _ = request.respond(response);
+        }
+    })?;
+    Ok(port)
+}
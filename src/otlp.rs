@@ -0,0 +1,417 @@
+// --- OTLP Exporter ---
+//
+// Sends spans to an OpenTelemetry Collector over gRPC instead of storing records.
+// A span is exported once its end record arrives; logs, events and exceptions
+// reported inside an open span become events of that span, and links its links.
+
+use crate::{DatabaseBackend, LongtraceError, Record, SpanPercentiles, EXCEPTION, LINK, STATUS_ERROR, STATUS_OK, STATUS_UPDATE};
+use opentelemetry_proto::tonic::collector::trace::v1::trace_service_client::TraceServiceClient;
+use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, InstrumentationScope, KeyValue};
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use opentelemetry_proto::tonic::trace::v1::{span, status, ResourceSpans, ScopeSpans, Span, Status};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tonic::transport::Channel;
+use uuid::Uuid;
+
+pub struct OtlpExporter {
+    pub endpoint: String,
+    pub service_name: String,
+    // Connected on first export, from inside the writer's runtime
+    client: Mutex<Option<TraceServiceClient<Channel>>>,
+    state: Mutex<ExportState>,
+    max_outbox_size: usize,
+}
+
+#[derive(Default)]
+struct ExportState {
+    // Span starts waiting for their end record
+    open: HashMap<Uuid, OpenSpan>,
+    // Finished spans not yet accepted by the collector, oldest first
+    outbox: VecDeque<Span>,
+}
+
+struct OpenSpan {
+    start: Record,
+    trace_id: Uuid,
+    events: Vec<Record>,
+    links: Vec<span::Link>,
+}
+
+impl OtlpExporter {
+    /// `endpoint` is an `http://host:port` URL; at most `max_outbox_size` finished
+    /// spans are kept while the collector is unreachable.
+    pub fn new(endpoint: String, service_name: String, max_outbox_size: usize) -> Self {
+        OtlpExporter {
+            endpoint,
+            service_name,
+            client: Mutex::new(None),
+            state: Mutex::new(ExportState::default()),
+            max_outbox_size,
+        }
+    }
+
+    fn collect(&self, state: &mut ExportState, record: Record) {
+        match record.record_type {
+            1 => {
+                // Children of a local span share its trace; a root continues its
+                // (possibly remote) parent's trace, or starts its own
+                let trace_id = match state.open.get(&record.parent_id) {
+                    Some(parent) => parent.trace_id,
+                    None if record.parent_id.is_nil() => record.span_id,
+                    None => record.parent_id,
+                };
+                state.open.insert(record.span_id, OpenSpan { start: record, trace_id, events: Vec::new(), links: Vec::new() });
+            }
+            2 => {
+                let span = match state.open.remove(&record.span_id) {
+                    Some(open) => to_span(&open.start, &record, open.trace_id, &open.events, open.links),
+                    // The start was reported before this exporter existed
+                    None => to_span(&record, &record, record.span_id, &[], Vec::new()),
+                };
+                self.push_outbox(state, span);
+            }
+            // The end record carries the final status
+            STATUS_UPDATE => {}
+            LINK => {
+                let link = to_link(state, &record);
+                match state.open.get_mut(&record.span_id) {
+                    Some(open) => open.links.push(link),
+                    None => {
+                        let span = to_span(&record, &record, record.span_id, &[], vec![link]);
+                        self.push_outbox(state, span);
+                    }
+                }
+            }
+            _ => match state.open.get_mut(&record.parent_id) {
+                Some(parent) => parent.events.push(record),
+                // Outside any open span there is nothing to attach to, so the
+                // record is exported as a span without duration
+                None => {
+                    let span = to_span(&record, &record, record.span_id, &[], Vec::new());
+                    self.push_outbox(state, span);
+                }
+            },
+        }
+    }
+
+    fn push_outbox(&self, state: &mut ExportState, span: Span) {
+        if state.outbox.len() >= self.max_outbox_size {
+            state.outbox.pop_front();
+        }
+        state.outbox.push_back(span);
+    }
+
+    fn export(&self, spans: Vec<Span>) -> Result<(), LongtraceError> {
+        let request = ExportTraceServiceRequest {
+            resource_spans: vec![ResourceSpans {
+                resource: Some(Resource {
+                    attributes: vec![key_value("service.name", string_value(self.service_name.clone()))],
+                    ..Default::default()
+                }),
+                scope_spans: vec![ScopeSpans {
+                    scope: Some(InstrumentationScope { name: "longtrace".to_string(), ..Default::default() }),
+                    spans,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+        };
+
+        // Called on the writer's blocking pool, which may block on its runtime
+        let runtime = tokio::runtime::Handle::current();
+        let mut client = self.client.lock().unwrap();
+        if client.is_none() {
+            let connected = runtime
+                .block_on(TraceServiceClient::connect(self.endpoint.clone()))
+                .map_err(|e| LongtraceError::Export(e.to_string()))?;
+            *client = Some(connected);
+        }
+        let result = runtime.block_on(client.as_mut().unwrap().export(request));
+        if let Err(status) = result {
+            // Reconnect on the next attempt
+            *client = None;
+            return Err(LongtraceError::Export(status.to_string()));
+        }
+        Ok(())
+    }
+}
+
+impl DatabaseBackend for OtlpExporter {
+    fn execute_batch(&self, batch: &mut Vec<Record>) -> Result<(), LongtraceError> {
+        // Records are consumed into spans right away; a failed export keeps the
+        // spans in the outbox and retries them with the next batch instead
+        let spans: Vec<Span> = {
+            let mut state = self.state.lock().unwrap();
+            for record in batch.drain(..) {
+                self.collect(&mut state, record);
+            }
+            state.outbox.drain(..).collect()
+        };
+        if spans.is_empty() {
+            return Ok(());
+        }
+
+        let count = spans.len();
+        self.export(spans.clone()).inspect_err(|_| {
+            let mut state = self.state.lock().unwrap();
+            for span in spans.into_iter().rev() {
+                if state.outbox.len() < self.max_outbox_size {
+                    state.outbox.push_front(span);
+                }
+            }
+            eprintln!("Keeping {} spans for the next OTLP export", count);
+        })
+    }
+
+    fn query_by_span(&self, _span_id: Uuid, _limit: Option<i64>) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the OTLP exporter"))
+    }
+
+    fn query_by_time_range(
+        &self,
+        _start: chrono::NaiveDateTime,
+        _end: chrono::NaiveDateTime,
+        _record_type: Option<i32>,
+        _limit: Option<i64>,
+    ) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the OTLP exporter"))
+    }
+
+    fn get_trace_tree(&self, _root_parent_id: Uuid) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the OTLP exporter"))
+    }
+
+    fn query_children(&self, _parent_id: Uuid, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the OTLP exporter"))
+    }
+
+    fn query_slow_spans(&self, _min_duration_ns: i64, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the OTLP exporter"))
+    }
+
+    fn span_percentiles(&self, _message_prefix: &str) -> Result<SpanPercentiles, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the OTLP exporter"))
+    }
+
+    fn search_by_message(&self, _pattern: &str, _use_regex: bool, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the OTLP exporter"))
+    }
+
+    fn search_fulltext(&self, _query: &str, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the OTLP exporter"))
+    }
+
+    fn cleanup_older_than(&self, _cutoff: chrono::NaiveDateTime) -> Result<u64, LongtraceError> {
+        Err(LongtraceError::Unsupported("cleanup is not supported by the OTLP exporter"))
+    }
+
+    fn vacuum(&self, _analyze: bool) -> Result<(), LongtraceError> {
+        Err(LongtraceError::Unsupported("vacuum is not supported by the OTLP exporter"))
+    }
+}
+
+/// OTLP span IDs are 8 bytes; the low half of a v7 UUID is the random part.
+fn span_id_bytes(id: Uuid) -> Vec<u8> {
+    id.as_bytes()[8..].to_vec()
+}
+
+// Records without `timestamp_ns` only have microseconds
+fn unix_nanos(record: &Record) -> u64 {
+    let nanos = record.timestamp_ns.unwrap_or_else(|| record.timestamp_utc().timestamp_nanos_opt().unwrap_or(0));
+    nanos.max(0) as u64
+}
+
+fn string_value(value: String) -> AnyValue {
+    AnyValue { value: Some(any_value::Value::StringValue(value)) }
+}
+
+fn key_value(key: &str, value: AnyValue) -> KeyValue {
+    KeyValue { key: key.to_string(), value: Some(value), ..Default::default() }
+}
+
+/// Top-level `attr` keys become attributes; nested values are kept as JSON text.
+fn attributes(record: &Record) -> Vec<KeyValue> {
+    let Some(serde_json::Value::Object(map)) = record.attr_value() else {
+        return Vec::new();
+    };
+    map.into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::Bool(b) => AnyValue { value: Some(any_value::Value::BoolValue(b)) },
+                serde_json::Value::Number(n) => match n.as_i64() {
+                    Some(i) => AnyValue { value: Some(any_value::Value::IntValue(i)) },
+                    None => AnyValue { value: Some(any_value::Value::DoubleValue(n.as_f64().unwrap_or(f64::NAN))) },
+                },
+                serde_json::Value::String(s) => string_value(s),
+                other => string_value(other.to_string()),
+            };
+            key_value(&key, value)
+        })
+        .collect()
+}
+
+/// The linked span's trace is only known while it is open here; otherwise its ID
+/// stands in, as it does for a root span's own trace.
+fn to_link(state: &ExportState, record: &Record) -> span::Link {
+    let linked = record.linked_span_id.unwrap_or_default();
+    let trace_id = state.open.get(&linked).map_or(linked, |open| open.trace_id);
+    span::Link {
+        trace_id: trace_id.as_bytes().to_vec(),
+        span_id: span_id_bytes(linked),
+        attributes: attributes(record).into_iter().filter(|kv| kv.key != "linked_span_id").collect(),
+        ..Default::default()
+    }
+}
+
+fn to_span(start: &Record, end: &Record, trace_id: Uuid, events: &[Record], links: Vec<span::Link>) -> Span {
+    // An exception that left the span marks it as failed unless a status was set
+    let exception = events.iter().find(|event| event.record_type == EXCEPTION);
+    let status = match (end.status, exception) {
+        (STATUS_OK, _) => Some(Status { code: status::StatusCode::Ok as i32, ..Default::default() }),
+        (STATUS_ERROR, _) | (_, Some(_)) => {
+            let message = match exception.and_then(|exception| exception.attr_value()) {
+                Some(attr) => attr["exception_message"].as_str().unwrap_or_default().to_string(),
+                None => String::new(),
+            };
+            Some(Status { message, code: status::StatusCode::Error as i32 })
+        }
+        _ => None,
+    };
+
+    Span {
+        trace_id: trace_id.as_bytes().to_vec(),
+        span_id: span_id_bytes(start.span_id),
+        parent_span_id: if start.parent_id.is_nil() { Vec::new() } else { span_id_bytes(start.parent_id) },
+        name: start.message.clone(),
+        kind: span::SpanKind::Internal as i32,
+        start_time_unix_nano: unix_nanos(start),
+        end_time_unix_nano: unix_nanos(end),
+        attributes: attributes(start),
+        events: events
+            .iter()
+            .map(|event| span::Event {
+                time_unix_nano: unix_nanos(event),
+                name: event.message.clone(),
+                attributes: attributes(event),
+                ..Default::default()
+            })
+            .collect(),
+        links,
+        status,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DatabaseOptions, RustDatabase, INFO};
+    use chrono::Utc;
+    use opentelemetry_proto::tonic::collector::trace::v1::trace_service_server::{TraceService, TraceServiceServer};
+    use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceResponse;
+    use serde_json::json;
+    use std::sync::Arc;
+    use tonic::{Request, Response};
+
+    #[derive(Clone, Default)]
+    struct Collector {
+        spans: Arc<Mutex<Vec<Span>>>,
+    }
+
+    #[tonic::async_trait]
+    impl TraceService for Collector {
+        async fn export(
+            &self,
+            request: Request<ExportTraceServiceRequest>,
+        ) -> Result<Response<ExportTraceServiceResponse>, tonic::Status> {
+            let mut spans = self.spans.lock().unwrap();
+            for resource in request.into_inner().resource_spans {
+                for scope in resource.scope_spans {
+                    spans.extend(scope.spans);
+                }
+            }
+            Ok(Response::new(ExportTraceServiceResponse::default()))
+        }
+    }
+
+    #[test]
+    fn test_spans_are_paired_and_exported() {
+        let collector = Collector::default();
+        let server_runtime = tokio::runtime::Runtime::new().unwrap();
+        let listener = server_runtime.block_on(tokio::net::TcpListener::bind("127.0.0.1:0")).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let service = TraceServiceServer::new(collector.clone());
+        server_runtime.spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(tonic::transport::server::TcpIncoming::from(listener))
+                .await
+        });
+
+        let db = RustDatabase::new(&format!("otlp://{}", addr), DatabaseOptions::default()).expect("Failed to create exporter");
+        let root = Uuid::now_v7();
+        let child = Uuid::now_v7();
+        db.report("root".to_string(), root, Uuid::nil(), Some(json!({"user": 1}).to_string()), 1, INFO).unwrap();
+        db.report("child".to_string(), child, root, None, 1, INFO).unwrap();
+        db.report("inside child".to_string(), Uuid::now_v7(), child, None, 0, INFO).unwrap();
+        let other_trace = Uuid::now_v7();
+        db.report_link("child".to_string(), child, other_trace).unwrap();
+        let exception = json!({"exception_type": "ValueError", "exception_message": "boom"}).to_string();
+        db.report("child".to_string(), Uuid::now_v7(), child, Some(exception), EXCEPTION, INFO).unwrap();
+        db.report("child".to_string(), child, root, None, 2, INFO).unwrap();
+        db.report_status("root".to_string(), root, STATUS_OK).unwrap();
+        db.report_span_end("root".to_string(), root, Uuid::nil(), None, STATUS_OK, None).unwrap();
+        db.flush().unwrap();
+
+        let spans = collector.spans.lock().unwrap();
+        assert_eq!(spans.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), ["child", "root"]);
+        let (child_span, root_span) = (&spans[0], &spans[1]);
+        assert_eq!(root_span.trace_id, root.as_bytes().to_vec());
+        assert_eq!(child_span.trace_id, root_span.trace_id);
+        assert_eq!(child_span.parent_span_id, root_span.span_id);
+        assert!(root_span.parent_span_id.is_empty());
+        assert!(root_span.start_time_unix_nano <= child_span.start_time_unix_nano);
+        assert!(child_span.end_time_unix_nano <= root_span.end_time_unix_nano);
+        assert!(root_span.attributes.iter().any(|kv| kv.key == "user"));
+        assert_eq!(child_span.events.iter().map(|e| e.name.as_str()).collect::<Vec<_>>(), ["inside child", "child"]);
+        assert_eq!(child_span.links.len(), 1);
+        assert_eq!(child_span.links[0].span_id, span_id_bytes(other_trace));
+        assert_eq!(child_span.links[0].trace_id, other_trace.as_bytes().to_vec());
+        let status = child_span.status.as_ref().unwrap();
+        assert_eq!(status.code, status::StatusCode::Error as i32);
+        assert_eq!(status.message, "boom");
+        assert_eq!(root_span.status.as_ref().unwrap().code, status::StatusCode::Ok as i32);
+        assert!(root_span.events.is_empty());
+
+        assert!(matches!(db.query_by_span(root, None), Err(LongtraceError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_spans_are_kept_while_collector_is_down() {
+        let exporter = OtlpExporter::new("http://127.0.0.1:1".to_string(), "test".to_string(), 1);
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+
+        let record = |record_type| Record {
+            span_id: Uuid::now_v7(),
+            parent_id: Uuid::nil(),
+            record_type,
+            timestamp: Utc::now().naive_utc(),
+            message: "log".to_string(),
+            attr: None,
+            severity: INFO,
+            linked_span_id: None,
+            status: 0,
+            duration_ns: None,
+            timestamp_ns: None,
+            signature: None,
+        };
+        let mut batch = vec![record(0), record(0)];
+        assert!(matches!(exporter.execute_batch(&mut batch), Err(LongtraceError::Export(_))));
+        assert!(batch.is_empty());
+        // Only the newest span fits in the outbox
+        assert_eq!(exporter.state.lock().unwrap().outbox.len(), 1);
+    }
+}
@@ -0,0 +1,194 @@
+// --- Redis Streams Backend ---
+//
+// Appends every record to a Redis stream, for setups that have Redis but neither
+// PostgreSQL nor Kafka. Each record becomes one stream entry whose fields are those
+// of the JSON export; the stream is trimmed to about `max_len` entries if set.
+// Streams are write-only from here, so nothing can be queried back.
+
+use crate::{DatabaseBackend, LongtraceError, Record, SpanPercentiles};
+use redis::ConnectionLike;
+use serde_json::Value;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+pub struct RedisStreamBackend {
+    pub client: redis::Client,
+    pub stream_key: String,
+    /// Approximate length the stream is trimmed to with `MAXLEN ~`
+    pub max_len: Option<u64>,
+    // Connected on first write, and again after a failed one
+    connection: Mutex<Option<redis::Connection>>,
+}
+
+impl RedisStreamBackend {
+    /// `url` is a `redis://` or `rediss://` URL; nothing is connected until the first batch.
+    pub fn new(url: &str, stream_key: String, max_len: Option<u64>) -> Result<Self, LongtraceError> {
+        let client = redis::Client::open(url).map_err(|e| LongtraceError::invalid_config(format!("invalid Redis URL: {}", e)))?;
+        Ok(RedisStreamBackend { client, stream_key, max_len, connection: Mutex::new(None) })
+    }
+
+    /// Sends one `XADD` per record in a single pipeline.
+    fn write_batch(&self, connection: &mut impl ConnectionLike, batch: &[Record]) -> Result<(), LongtraceError> {
+        let mut pipe = redis::pipe();
+        for record in batch {
+            let xadd = pipe.cmd("XADD").arg(&self.stream_key);
+            if let Some(max_len) = self.max_len {
+                xadd.arg("MAXLEN").arg("~").arg(max_len);
+            }
+            xadd.arg("*");
+            for (field, value) in fields(record)? {
+                xadd.arg(field).arg(value);
+            }
+        }
+        pipe.query::<()>(connection)?;
+        Ok(())
+    }
+}
+
+/// The record's JSON export fields in key order, strings as is and the rest as JSON text.
+fn fields(record: &Record) -> Result<Vec<(String, String)>, LongtraceError> {
+    let Value::Object(map) = serde_json::to_value(record)? else {
+        unreachable!("a record serializes to an object");
+    };
+    Ok(map
+        .into_iter()
+        .map(|(field, value)| match value {
+            Value::String(s) => (field, s),
+            other => (field, other.to_string()),
+        })
+        .collect())
+}
+
+impl DatabaseBackend for RedisStreamBackend {
+    fn execute_batch(&self, batch: &mut Vec<Record>) -> Result<(), LongtraceError> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            *connection = Some(self.client.get_connection()?);
+        }
+        // A pipeline cut off midway may have added some entries; the retry adds them
+        // again, so delivery is at least once
+        self.write_batch(connection.as_mut().unwrap(), batch).inspect_err(|_| *connection = None)?;
+        batch.clear();
+        Ok(())
+    }
+
+    fn query_by_span(&self, _span_id: Uuid, _limit: Option<i64>) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Redis Streams backend"))
+    }
+
+    fn query_by_time_range(
+        &self,
+        _start: chrono::NaiveDateTime,
+        _end: chrono::NaiveDateTime,
+        _record_type: Option<i32>,
+        _limit: Option<i64>,
+    ) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Redis Streams backend"))
+    }
+
+    fn get_trace_tree(&self, _root_parent_id: Uuid) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Redis Streams backend"))
+    }
+
+    fn query_children(&self, _parent_id: Uuid, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Redis Streams backend"))
+    }
+
+    fn query_slow_spans(&self, _min_duration_ns: i64, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Redis Streams backend"))
+    }
+
+    fn span_percentiles(&self, _message_prefix: &str) -> Result<SpanPercentiles, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Redis Streams backend"))
+    }
+
+    fn search_by_message(&self, _pattern: &str, _use_regex: bool, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Redis Streams backend"))
+    }
+
+    fn search_fulltext(&self, _query: &str, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("queries are not supported by the Redis Streams backend"))
+    }
+
+    fn cleanup_older_than(&self, _cutoff: chrono::NaiveDateTime) -> Result<u64, LongtraceError> {
+        Err(LongtraceError::Unsupported("cleanup is not supported by the Redis Streams backend"))
+    }
+
+    fn vacuum(&self, _analyze: bool) -> Result<(), LongtraceError> {
+        Err(LongtraceError::Unsupported("vacuum is not supported by the Redis Streams backend"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Backend, INFO};
+    use redis_test::{MockCmd, MockRedisConnection};
+    use serde_json::json;
+
+    #[test]
+    fn test_redis_connection_string() {
+        let backend = Backend::from_connection_string("redis://localhost:6379?stream=traces").unwrap();
+        assert_eq!(backend, Backend::Redis { url: "redis://localhost:6379".to_string(), stream_key: "traces".to_string(), max_len: None });
+        let backend = Backend::from_connection_string("rediss://:secret@cache:6380/2?maxlen=100000&stream=traces").unwrap();
+        assert_eq!(
+            backend,
+            Backend::Redis { url: "rediss://:secret@cache:6380/2".to_string(), stream_key: "traces".to_string(), max_len: Some(100_000) }
+        );
+        for invalid in ["redis://localhost:6379", "redis://localhost?stream=traces&maxlen=many", "redis://localhost?stream=traces&db=1"] {
+            assert!(matches!(Backend::from_connection_string(invalid), Err(LongtraceError::InvalidConfig { .. })), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_redis_stream_backend() {
+        let backend = RedisStreamBackend::new("redis://localhost:6379", "traces".to_string(), Some(1000)).unwrap();
+        let span_id = Uuid::now_v7();
+        let timestamp = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        let records = vec![
+            Record::builder().span_id(span_id).message("Cache miss").severity(INFO).timestamp(timestamp).attr(json!({"key": "user:1"})).build(),
+            Record::builder().span_id(span_id).record_type(2).severity(INFO).timestamp(timestamp).duration_ns(1500).build(),
+        ];
+
+        let span = span_id.to_string();
+        let nil = Uuid::nil().to_string();
+        let expected_fields = [
+            vec![
+                ("attr", r#"{"key":"user:1"}"#),
+                ("message", "Cache miss"),
+                ("parent_id", &nil),
+                ("record_type", "0"),
+                ("severity", "1"),
+                ("span_id", &span),
+                ("status", "0"),
+                ("timestamp", "2024-05-01T12:00:00"),
+            ],
+            vec![
+                ("duration_ns", "1500"),
+                ("message", ""),
+                ("parent_id", &nil),
+                ("record_type", "2"),
+                ("severity", "1"),
+                ("span_id", &span),
+                ("status", "0"),
+                ("timestamp", "2024-05-01T12:00:00"),
+            ],
+        ];
+        let mut expected = redis::pipe();
+        for fields in expected_fields {
+            let xadd = expected.cmd("XADD").arg("traces").arg("MAXLEN").arg("~").arg(1000).arg("*");
+            for (field, value) in fields {
+                xadd.arg(field).arg(value);
+            }
+        }
+        let mut connection = MockRedisConnection::new([MockCmd::with_values(expected, Ok(vec!["1714564800000-0", "1714564800000-1"]))]);
+        backend.write_batch(&mut connection, &records).unwrap();
+
+        // Nothing listens on port 1; the batch is kept for the retry
+        let unreachable = RedisStreamBackend::new("redis://127.0.0.1:1", "traces".to_string(), None).unwrap();
+        let mut batch = records;
+        assert!(matches!(unreachable.execute_batch(&mut batch), Err(LongtraceError::Redis(_))));
+        assert_eq!(batch.len(), 2);
+        assert!(matches!(unreachable.query_by_span(span_id, None), Err(LongtraceError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,120 @@
+// --- Scrubbing ---
+//
+// Removes passwords, tokens and personal data that callers logged by mistake. The
+// writers apply the rules to every record before it reaches the backend, so neither
+// the tables nor the dead letter queue hold the original values.
+
+use crate::{LongtraceError, Record};
+use regex::Regex;
+use serde_json::Value;
+
+/// What replaces a scrubbed value.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// A rule applied to every record before it is written.
+#[derive(Debug, Clone)]
+pub enum ScrubRule {
+    /// Replaces the value of every `attr` key with this name, ignoring case and at any
+    /// depth, with `"[REDACTED]"`
+    RedactJsonKey(String),
+    /// Replaces the matches of `pattern` in `message`; `replacement` may refer to
+    /// capture groups as `$1`
+    RegexReplace { pattern: Regex, replacement: String },
+}
+
+impl ScrubRule {
+    /// A `RegexReplace` rule, failing with `InvalidConfig` if `pattern` is not a valid
+    /// regular expression.
+    pub fn regex(pattern: &str, replacement: &str) -> Result<Self, LongtraceError> {
+        let pattern = Regex::new(pattern).map_err(|e| LongtraceError::invalid_config(format!("invalid scrub pattern: {}", e)))?;
+        Ok(ScrubRule::RegexReplace { pattern, replacement: replacement.to_string() })
+    }
+}
+
+/// Applies `rules` to the `message` and `attr` of `record`.
+pub fn scrub(record: &mut Record, rules: &[ScrubRule]) {
+    let mut keys = Vec::new();
+    for rule in rules {
+        match rule {
+            ScrubRule::RedactJsonKey(key) => keys.push(key.as_str()),
+            ScrubRule::RegexReplace { pattern, replacement } => {
+                if let std::borrow::Cow::Owned(message) = pattern.replace_all(&record.message, replacement.as_str()) {
+                    record.message = message;
+                }
+            }
+        }
+    }
+
+    if keys.is_empty() {
+        return;
+    }
+    // `attr` that is not JSON has no keys to redact
+    let Some(mut attr) = record.attr.as_deref().and_then(|attr| serde_json::from_str::<Value>(attr).ok()) else {
+        return;
+    };
+    if redact_keys(&mut attr, &keys) {
+        record.attr = Some(attr.to_string());
+    }
+}
+
+/// Returns whether any value was redacted.
+fn redact_keys(value: &mut Value, keys: &[&str]) -> bool {
+    let mut redacted = false;
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if keys.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+                    *value = Value::String(REDACTED.to_string());
+                    redacted = true;
+                } else {
+                    redacted |= redact_keys(value, keys);
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                redacted |= redact_keys(item, keys);
+            }
+        }
+        _ => {}
+    }
+    redacted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_scrub() {
+        let rules = vec![
+            ScrubRule::RedactJsonKey("password".to_string()),
+            ScrubRule::RedactJsonKey("token".to_string()),
+            ScrubRule::regex(r"\d{16}", REDACTED).unwrap(),
+        ];
+        let mut record = Record::builder()
+            .message("Charged card 4111111111111111")
+            .attr(json!({"user": "alice", "Password": "secret", "nested": [{"token": 42}], "count": 3}))
+            .build();
+        scrub(&mut record, &rules);
+        assert_eq!(record.message, "Charged card [REDACTED]");
+        assert_eq!(
+            record.attr_value().unwrap(),
+            json!({"user": "alice", "Password": "[REDACTED]", "nested": [{"token": "[REDACTED]"}], "count": 3})
+        );
+
+        // Untouched attr keeps its original text
+        let mut record = Record::builder().message("Nothing to hide").build();
+        record.attr = Some(r#"{"b": 1,  "a": 2}"#.to_string());
+        scrub(&mut record, &rules);
+        assert_eq!(record.attr.as_deref(), Some(r#"{"b": 1,  "a": 2}"#));
+
+        let groups = ScrubRule::regex(r"(user=)\w+", "${1}***").unwrap();
+        let mut record = Record::builder().message("Login user=alice").build();
+        scrub(&mut record, &[groups]);
+        assert_eq!(record.message, "Login user=***");
+
+        assert!(matches!(ScrubRule::regex("(", REDACTED), Err(LongtraceError::InvalidConfig { .. })));
+    }
+}
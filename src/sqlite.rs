@@ -0,0 +1,435 @@
+// --- SQLite Backend ---
+//
+// A single-file alternative to PostgreSQL for local development and embedded use.
+// UUIDs are stored as hyphenated text and timestamps as fixed-width UTC text, so
+// both sort and compare the same way as in PostgreSQL.
+
+use crate::{like_escape, pool_builder, DatabaseBackend, DatabaseOptions, LongtraceError, Record, RecordCursor, RecordQuery, SpanPercentiles, RECORD_COLUMNS, LINK, MAX_TRACE_DEPTH};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::types::Type;
+use rusqlite::{params, Row};
+use std::path::Path;
+use uuid::Uuid;
+
+const SCHEMA: &str = r#"
+    CREATE TABLE IF NOT EXISTS records (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        span_id TEXT,
+        parent_id TEXT,
+        type INTEGER,
+        timestamp TEXT,
+        message TEXT,
+        attr TEXT,
+        severity INTEGER DEFAULT 1,
+        linked_span_id TEXT,
+        status INTEGER DEFAULT 0,
+        duration_ns INTEGER,
+        timestamp_ns INTEGER,
+        signature TEXT
+    );
+    CREATE INDEX IF NOT EXISTS idx_records_parent_id ON records(parent_id);
+    CREATE INDEX IF NOT EXISTS idx_records_span_id ON records(span_id);
+    CREATE INDEX IF NOT EXISTS idx_records_timestamp ON records(timestamp);
+    CREATE INDEX IF NOT EXISTS idx_records_span_type ON records(span_id, type);
+    CREATE INDEX IF NOT EXISTS idx_records_severity ON records(severity);
+"#;
+
+/// Columns added to `SCHEMA` after its first release, with their definitions.
+const ADDED_COLUMNS: &[(&str, &str)] = &[("linked_span_id", "TEXT"), ("status", "INTEGER DEFAULT 0"), ("duration_ns", "INTEGER"), ("timestamp_ns", "INTEGER"), ("signature", "TEXT")];
+
+/// Indexes on `ADDED_COLUMNS`, created once the columns exist.
+const ADDED_INDEXES: &str = "CREATE INDEX IF NOT EXISTS idx_records_duration ON records(duration_ns);";
+
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.6f";
+
+const SELECT_COLUMNS: &str = "SELECT span_id, parent_id, type, timestamp, message, attr, severity, linked_span_id, status, duration_ns, timestamp_ns, signature FROM records";
+
+pub struct SqliteBackend {
+    pub(crate) pool: Pool<SqliteConnectionManager>,
+}
+
+impl SqliteBackend {
+    pub fn open(path: &Path, options: &DatabaseOptions) -> Result<Self, LongtraceError> {
+        if options.partitioning.is_some() {
+            return Err(LongtraceError::invalid_config("partitioning is not supported by the SQLite backend".to_string()));
+        }
+        if options.table_prefix.is_some() || options.pg_schema.is_some() {
+            return Err(LongtraceError::invalid_config("table_prefix and pg_schema are not supported by the SQLite backend"));
+        }
+
+        // WAL lets the query functions read while the writer is inserting
+        let manager = SqliteConnectionManager::file(path)
+            .with_init(|conn| conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;"));
+        let pool: Pool<SqliteConnectionManager> = pool_builder(options, 4)?.build(manager)?;
+        let conn = pool.get()?;
+        conn.execute_batch(SCHEMA).map_err(LongtraceError::Sqlite)?;
+        // Files created by older versions lack the newer columns
+        for (column, definition) in ADDED_COLUMNS {
+            add_column(&conn, column, definition)?;
+        }
+        conn.execute_batch(ADDED_INDEXES).map_err(LongtraceError::Sqlite)?;
+        drop(conn);
+
+        Ok(SqliteBackend { pool })
+    }
+
+    fn query(&self, sql: &str, params: impl rusqlite::Params) -> Result<Vec<Record>, LongtraceError> {
+        let conn = self.pool.get()?;
+        let mut statement = conn.prepare(sql).map_err(LongtraceError::Sqlite)?;
+        let records = statement
+            .query_map(params, record_from_row)
+            .map_err(LongtraceError::Sqlite)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LongtraceError::Sqlite)?;
+        Ok(records)
+    }
+}
+
+/// Adds `column` to `records` unless it is there already.
+pub(crate) fn add_column(conn: &rusqlite::Connection, column: &str, definition: &str) -> Result<(), LongtraceError> {
+    let exists: bool = conn
+        .query_row("SELECT COUNT(*) > 0 FROM pragma_table_info('records') WHERE name = ?1", [column], |row| row.get(0))
+        .map_err(LongtraceError::Sqlite)?;
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE records ADD COLUMN {} {}", column, definition))
+            .map_err(LongtraceError::Sqlite)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn format_timestamp(timestamp: chrono::NaiveDateTime) -> String {
+    timestamp.format(TIMESTAMP_FORMAT).to_string()
+}
+
+/// Reads the columns of `SELECT_COLUMNS`, in order, from the start of `row`.
+pub(crate) fn record_from_row(row: &Row<'_>) -> rusqlite::Result<Record> {
+    let uuid = |index: usize| -> rusqlite::Result<Uuid> {
+        let text: String = row.get(index)?;
+        Uuid::parse_str(&text).map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+    };
+    let timestamp: String = row.get(3)?;
+    let timestamp = chrono::NaiveDateTime::parse_from_str(&timestamp, TIMESTAMP_FORMAT)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?;
+
+    Ok(Record {
+        span_id: uuid(0)?,
+        parent_id: uuid(1)?,
+        record_type: row.get(2)?,
+        timestamp,
+        message: row.get(4)?,
+        attr: row.get(5)?,
+        severity: row.get(6)?,
+        linked_span_id: row.get::<_, Option<String>>(7)?.map(|_| uuid(7)).transpose()?,
+        status: row.get(8)?,
+        duration_ns: row.get(9)?,
+        timestamp_ns: row.get(10)?,
+        signature: row.get(11)?,
+    })
+}
+
+impl DatabaseBackend for SqliteBackend {
+    fn execute_batch(&self, batch: &mut Vec<Record>) -> Result<(), LongtraceError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        // One transaction per batch, so a failure keeps the whole batch
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction().map_err(LongtraceError::Sqlite)?;
+        {
+            let mut insert = tx
+                .prepare_cached("INSERT INTO records (span_id, parent_id, type, timestamp, message, attr, severity, linked_span_id, status, duration_ns, timestamp_ns, signature) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)")
+                .map_err(LongtraceError::Sqlite)?;
+            for record in batch.iter() {
+                insert
+                    .execute(params![
+                        record.span_id.to_string(),
+                        record.parent_id.to_string(),
+                        record.record_type,
+                        format_timestamp(record.timestamp),
+                        record.message,
+                        record.attr_value().map(|v| v.to_string()),
+                        record.severity,
+                        record.linked_span_id.map(|id| id.to_string()),
+                        record.status,
+                        record.duration_ns,
+                        record.timestamp_ns,
+                        record.signature,
+                    ])
+                    .map_err(LongtraceError::Sqlite)?;
+            }
+        }
+        tx.commit().map_err(LongtraceError::Sqlite)?;
+
+        batch.clear();
+        Ok(())
+    }
+
+    fn query_by_span(&self, span_id: Uuid, limit: Option<i64>) -> Result<Vec<Record>, LongtraceError> {
+        // A negative limit returns every row
+        self.query(
+            &format!("{} WHERE span_id = ?1 ORDER BY timestamp, id LIMIT ?2", SELECT_COLUMNS),
+            params![span_id.to_string(), limit.unwrap_or(-1)],
+        )
+    }
+
+    fn query_by_time_range(
+        &self,
+        start: chrono::NaiveDateTime,
+        end: chrono::NaiveDateTime,
+        record_type: Option<i32>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Record>, LongtraceError> {
+        self.query(
+            &format!("{} WHERE timestamp BETWEEN ?1 AND ?2 AND (?3 IS NULL OR type = ?3) ORDER BY timestamp, id LIMIT ?4", SELECT_COLUMNS),
+            params![format_timestamp(start), format_timestamp(end), record_type, limit.unwrap_or(-1)],
+        )
+    }
+
+    fn query_records(&self, query: &RecordQuery) -> Result<Vec<Record>, LongtraceError> {
+        Ok(self.query_records_after(query, None)?.into_iter().map(|(_, record)| record).collect())
+    }
+
+    fn query_records_after(&self, query: &RecordQuery, after: Option<RecordCursor>) -> Result<Vec<(RecordCursor, Record)>, LongtraceError> {
+        let (start, end) = query.time_range();
+        let (after_timestamp, after_id) = after.unzip();
+        let conn = self.pool.get()?;
+        let mut statement = conn
+            .prepare(&format!(
+                "SELECT {}, id FROM records WHERE timestamp BETWEEN ?1 AND ?2 AND (?3 IS NULL OR type = ?3) AND (?4 IS NULL OR span_id = ?4) \
+                 AND (?5 IS NULL OR span_id LIKE ?5 || '%' ESCAPE '\\') AND (?6 IS NULL OR (timestamp, id) > (?6, ?7)) \
+                 ORDER BY timestamp, id LIMIT ?8",
+                RECORD_COLUMNS
+            ))
+            .map_err(LongtraceError::Sqlite)?;
+        let params = params![
+            format_timestamp(start),
+            format_timestamp(end),
+            query.record_type,
+            query.span_id.map(|id| id.to_string()),
+            query.span_id_prefix.as_deref().map(like_escape),
+            after_timestamp.map(format_timestamp),
+            after_id,
+            query.limit.unwrap_or(-1)
+        ];
+        let records = statement
+            .query_map(params, |row| {
+                let record = record_from_row(row)?;
+                Ok(((record.timestamp, row.get(12)?), record))
+            })
+            .map_err(LongtraceError::Sqlite)?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(LongtraceError::Sqlite)?;
+        Ok(records)
+    }
+
+    fn query_slow_spans(&self, min_duration_ns: i64, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.query(
+            &format!("{} WHERE type = 2 AND duration_ns >= ?1 ORDER BY duration_ns DESC, id LIMIT ?2", SELECT_COLUMNS),
+            params![min_duration_ns, limit],
+        )
+    }
+
+    fn span_percentiles(&self, message_prefix: &str) -> Result<SpanPercentiles, LongtraceError> {
+        // SQLite has no percentile_cont, so the matching durations are ranked here
+        let conn = self.pool.get()?;
+        let mut statement = conn
+            .prepare(
+                "SELECT duration_ns FROM records WHERE type = 2 AND duration_ns IS NOT NULL \
+                 AND substr(message, 1, length(?1)) = ?1 ORDER BY duration_ns",
+            )
+            .map_err(LongtraceError::Sqlite)?;
+        let durations = statement
+            .query_map([message_prefix], |row| row.get(0))
+            .map_err(LongtraceError::Sqlite)?
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(LongtraceError::Sqlite)?;
+        Ok(SpanPercentiles::from_sorted(&durations))
+    }
+
+    fn search_by_message(&self, pattern: &str, use_regex: bool, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        if use_regex {
+            return Err(LongtraceError::Unsupported("regex search is not supported by the SQLite backend"));
+        }
+        // LIKE already ignores ASCII case in SQLite
+        self.query(
+            &format!("{} WHERE message LIKE '%' || ?1 || '%' ESCAPE '\\' ORDER BY timestamp DESC, id DESC LIMIT ?2", SELECT_COLUMNS),
+            params![like_escape(pattern), limit],
+        )
+    }
+
+    fn search_fulltext(&self, _query: &str, _limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        Err(LongtraceError::Unsupported("full-text search is not supported by the SQLite backend"))
+    }
+
+    fn get_trace_tree(&self, root_parent_id: Uuid) -> Result<Vec<Record>, LongtraceError> {
+        // SQLite has no arrays, so the path of visited spans is a '/'-separated string.
+        // LINK rows are their own parent: they pass the cycle check but are not followed.
+        let query = format!(
+            r#"
+            WITH RECURSIVE trace(id, span_id, type, depth, path) AS (
+                SELECT id, span_id, type, 1, '/' || span_id || '/'
+                FROM records
+                WHERE parent_id = ?1
+                UNION
+                SELECT r.id, r.span_id, r.type, t.depth + 1, t.path || r.span_id || '/'
+                FROM records r
+                JOIN trace t ON r.parent_id = t.span_id
+                WHERE t.depth < ?2 AND t.type <> {1} AND (instr(t.path, '/' || r.span_id || '/') = 0 OR r.type = {1})
+            )
+            {} WHERE id IN (SELECT id FROM trace) ORDER BY timestamp, id
+            "#,
+            SELECT_COLUMNS,
+            LINK
+        );
+        self.query(&query, params![root_parent_id.to_string(), MAX_TRACE_DEPTH])
+    }
+
+    fn query_children(&self, parent_id: Uuid, limit: i64) -> Result<Vec<Record>, LongtraceError> {
+        self.query(
+            &format!("{} WHERE parent_id = ?1 ORDER BY timestamp, id LIMIT ?2", SELECT_COLUMNS),
+            params![parent_id.to_string(), limit],
+        )
+    }
+
+    fn cleanup_older_than(&self, cutoff: chrono::NaiveDateTime) -> Result<u64, LongtraceError> {
+        let conn = self.pool.get()?;
+        let deleted = conn
+            .execute("DELETE FROM records WHERE timestamp < ?1", params![format_timestamp(cutoff)])
+            .map_err(LongtraceError::Sqlite)?;
+        Ok(deleted as u64)
+    }
+
+    fn vacuum(&self, analyze: bool) -> Result<(), LongtraceError> {
+        let conn = self.pool.get()?;
+        let command = if analyze { "VACUUM; ANALYZE" } else { "VACUUM" };
+        conn.execute_batch(command).map_err(LongtraceError::Sqlite)
+    }
+
+    fn pool_state(&self) -> Option<r2d2::State> {
+        Some(self.pool.state())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RustDatabase, INFO};
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn temp_db_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("longtrace-{}.db", Uuid::now_v7()))
+    }
+
+    #[test]
+    fn test_sqlite_round_trip() {
+        let path = temp_db_path();
+        let db = RustDatabase::new(path.to_str().unwrap(), DatabaseOptions::default()).expect("Failed to open SQLite database");
+        assert!(db.postgres_pool().is_none());
+
+        let root_parent_id = Uuid::now_v7();
+        let span_id = Uuid::now_v7();
+        let start = Utc::now().naive_utc();
+        db.report("Span".to_string(), span_id, root_parent_id, None, 1, INFO).unwrap();
+        let attr = json!({"key": "value"}).to_string();
+        db.report("Child".to_string(), Uuid::now_v7(), span_id, Some(attr), 0, INFO).unwrap();
+        let linked = Uuid::now_v7();
+        db.report_link("Span".to_string(), span_id, linked).unwrap();
+        db.report_span_end("Span".to_string(), span_id, root_parent_id, None, 0, Some(std::time::Duration::from_micros(1500))).unwrap();
+        db.flush().unwrap();
+        let end = Utc::now().naive_utc();
+
+        let span = db.query_by_span(span_id, None).unwrap();
+        assert_eq!(span.iter().map(|r| r.record_type).collect::<Vec<_>>(), [1, LINK, 2]);
+        assert_eq!(span[0].parent_id, root_parent_id);
+        assert_eq!(span[1].linked_span_id, Some(linked));
+        assert_eq!(span[0].linked_span_id, None);
+        assert_eq!((span[0].duration_ns, span[2].duration_ns), (None, Some(1_500_000)));
+        let slow = db.get_slow_spans(1_000_000, 10).unwrap();
+        assert_eq!((slow.len(), slow[0].span_id), (1, span_id));
+        assert!(db.get_slow_spans(2_000_000, 10).unwrap().is_empty());
+        assert_eq!(db.span_percentiles("Sp").unwrap().p50_ns, 1_500_000);
+        assert_eq!(db.search_by_message("HIL", false, 10).unwrap()[0].message, "Child");
+        assert!(db.search_by_message("C_ild", false, 10).unwrap().is_empty());
+        assert!(matches!(db.search_by_message("C.ild", true, 10), Err(LongtraceError::Unsupported(_))));
+
+        let trace = db.get_trace_tree(root_parent_id).unwrap();
+        assert_eq!(trace.iter().map(|r| r.message.as_str()).collect::<Vec<_>>(), ["Span", "Child", "Span", "Span"]);
+        let child_attr: serde_json::Value = serde_json::from_str(trace[1].attr.as_deref().unwrap()).unwrap();
+        assert_eq!(child_attr["key"], "value");
+        assert_eq!(db.get_children(root_parent_id, true, 10).unwrap().len(), 2);
+        assert_eq!(db.get_children(span_id, true, 1).unwrap()[0].message, "Child");
+
+        assert_eq!(db.query_by_time_range(start, end, Some(0), None).unwrap().len(), 1);
+        let prefix = span_id.to_string()[..34].to_string();
+        let query = crate::RecordQuery { span_id_prefix: Some(prefix), limit: Some(2), ..Default::default() };
+        let records = db.backend.query_records(&query).unwrap();
+        assert_eq!(records.iter().map(|r| r.record_type).collect::<Vec<_>>(), [1, LINK]);
+        let query = crate::RecordQuery { span_id_prefix: Some("%".to_string()), ..Default::default() };
+        assert!(db.backend.query_records(&query).unwrap().is_empty());
+        assert_eq!(db.cleanup_older_than(end + chrono::Duration::seconds(1)).unwrap(), 4);
+        db.vacuum(true).unwrap();
+        assert!(db.query_by_span(span_id, None).unwrap().is_empty());
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_url_and_partitioning() {
+        let path = temp_db_path();
+        let url = format!("sqlite://{}", path.display());
+        let options = DatabaseOptions { partitioning: Some(crate::PartitionStrategy::Daily), ..Default::default() };
+        assert!(matches!(RustDatabase::new(&url, options), Err(LongtraceError::InvalidConfig { .. })));
+        let options = DatabaseOptions { table_prefix: Some("svc_a".to_string()), ..Default::default() };
+        assert!(matches!(RustDatabase::new(&url, options), Err(LongtraceError::InvalidConfig { .. })));
+
+        let db = RustDatabase::new(&url, DatabaseOptions::default()).expect("Failed to open SQLite database");
+        assert_eq!(db.db_name, path.display().to_string());
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_sqlite_export_pages() {
+        let path = temp_db_path();
+        let db = RustDatabase::new(path.to_str().unwrap(), DatabaseOptions::default()).expect("Failed to open SQLite database");
+
+        // Sharing one timestamp, the chunks can only be told apart by id
+        let timestamp = chrono::DurationRound::duration_trunc(Utc::now().naive_utc(), chrono::Duration::microseconds(1)).unwrap();
+        let span_id = Uuid::now_v7();
+        let records: Vec<Record> = (0..2500)
+            .map(|i| Record {
+                span_id,
+                parent_id: Uuid::nil(),
+                record_type: 0,
+                timestamp,
+                message: format!("Page {}", i),
+                attr: None,
+                severity: INFO,
+                linked_span_id: None,
+                status: 0,
+                duration_ns: None,
+                timestamp_ns: None,
+                signature: None,
+            })
+            .collect();
+        let json_path = std::env::temp_dir().join(format!("longtrace-{}.json", Uuid::now_v7()));
+        std::fs::write(&json_path, serde_json::to_string(&records).unwrap()).unwrap();
+        assert_eq!(db.import_from_json(&json_path).unwrap(), 2500);
+
+        assert_eq!(db.export_to_json(&json_path, crate::RecordQuery::default()).unwrap(), 2500);
+        let exported: Vec<Record> = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(exported, records);
+        let query = crate::RecordQuery { limit: Some(1500), ..Default::default() };
+        assert_eq!(db.export_to_json(&json_path, query).unwrap(), 1500);
+        let exported: Vec<Record> = serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(exported.last().unwrap().message, "Page 1499");
+
+        drop(db);
+        let _ = std::fs::remove_file(&json_path);
+        let _ = std::fs::remove_file(&path);
+    }
+}
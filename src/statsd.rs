@@ -0,0 +1,83 @@
+// --- StatsD Metrics ---
+//
+// Sends the writers' counters to a StatsD server (Datadog, Telegraf and the like)
+// after every batch. The metrics go out as UDP datagrams from a non-blocking socket,
+// so a slow or missing server never holds up a writer; what cannot be sent is dropped.
+
+use crate::LongtraceError;
+use cadence::{Counted, Gauged, MetricSink, StatsdClient, Timed, UdpMetricSink};
+use std::net::UdpSocket;
+use std::panic::RefUnwindSafe;
+use std::time::Duration;
+
+/// Where the writers send their metrics; every name starts with `prefix` and a dot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatsdConfig {
+    pub host: String,
+    pub port: u16,
+    pub prefix: String,
+}
+
+pub(crate) struct StatsdEmitter {
+    client: StatsdClient,
+}
+
+impl std::fmt::Debug for StatsdEmitter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StatsdEmitter(..)")
+    }
+}
+
+impl StatsdEmitter {
+    pub(crate) fn connect(config: &StatsdConfig) -> Result<Self, LongtraceError> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_nonblocking(true)?;
+        let sink = UdpMetricSink::from((config.host.as_str(), config.port), socket).map_err(|e| {
+            LongtraceError::invalid_config(format!("cannot resolve the StatsD server {}:{}: {}", config.host, config.port, e))
+        })?;
+        Ok(Self::from_sink(&config.prefix, sink))
+    }
+
+    pub(crate) fn from_sink(prefix: &str, sink: impl MetricSink + Send + Sync + RefUnwindSafe + 'static) -> Self {
+        StatsdEmitter { client: StatsdClient::from_sink(prefix, sink) }
+    }
+
+    /// Reports one written batch; `pending` is what the writer still holds afterwards.
+    pub(crate) fn record_flush(&self, pending: usize, inserted: u64, failed: u64, elapsed: Duration) {
+        // Metrics are best effort; a dropped datagram is not worth a log line per batch
+        let _ = self.client.gauge("pending_records", pending as u64);
+        let _ = self.client.count("records_inserted", inserted as i64);
+        if failed > 0 {
+            let _ = self.client.count("records_failed", failed as i64);
+        }
+        let _ = self.client.time("flush_duration_ms", elapsed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cadence::SpyMetricSink;
+
+    #[test]
+    fn test_record_flush() {
+        let (metrics, sink) = SpyMetricSink::new();
+        let emitter = StatsdEmitter::from_sink("checkout", sink);
+        emitter.record_flush(3, 1024, 0, Duration::from_millis(42));
+        emitter.record_flush(0, 10, 2, Duration::from_micros(500));
+
+        let lines: Vec<String> = metrics.try_iter().map(|bytes| String::from_utf8(bytes).unwrap()).collect();
+        assert_eq!(
+            lines,
+            [
+                "checkout.pending_records:3|g",
+                "checkout.records_inserted:1024|c",
+                "checkout.flush_duration_ms:42|ms",
+                "checkout.pending_records:0|g",
+                "checkout.records_inserted:10|c",
+                "checkout.records_failed:2|c",
+                "checkout.flush_duration_ms:0|ms",
+            ]
+        );
+    }
+}
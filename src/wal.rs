@@ -0,0 +1,233 @@
+// --- Write-Ahead Log ---
+//
+// Keeps the records a writer has taken on in a file until they are written, so they
+// survive a crash and a database that stays down longer than the process. Each record
+// is a little-endian u32 length followed by its JSON export, as it is written after
+// scrubbing, encryption and signing. Every writer has its own log, which is emptied
+// once a flush leaves the writer holding nothing; what is left is replayed on the
+// next start. Records that failed every retry move to a log of their own,
+// `<path>.dead`, where they stay until they are drained.
+
+use crate::{LongtraceError, Record};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+
+pub(crate) struct WriteAheadLog {
+    file: File,
+    // Whether anything may have been appended since the last truncation
+    dirty: bool,
+}
+
+impl WriteAheadLog {
+    /// Opens the log of writer `worker`: `path` itself for the first writer and
+    /// `<path>.<worker>` for the others.
+    pub(crate) fn open(path: &Path, worker: usize) -> Result<Self, LongtraceError> {
+        Self::open_file(&worker_path(path, worker))
+    }
+
+    fn open_file(path: &Path) -> Result<Self, LongtraceError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let dirty = file.metadata()?.len() > 0;
+        Ok(WriteAheadLog { file, dirty })
+    }
+
+    /// Appends `records` and syncs them to disk before returning.
+    pub(crate) fn append(&mut self, records: &[Record]) -> Result<(), LongtraceError> {
+        let mut frames = Vec::new();
+        for record in records {
+            let json = serde_json::to_vec(record)?;
+            frames.extend_from_slice(&(json.len() as u32).to_le_bytes());
+            frames.extend_from_slice(&json);
+        }
+        self.dirty = true;
+        self.file.write_all(&frames)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    /// Empties the log once everything in it has been written.
+    pub(crate) fn truncate(&mut self) -> Result<(), LongtraceError> {
+        if self.dirty {
+            self.file.set_len(0)?;
+            self.file.sync_data()?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+fn dead_letter_path(path: &Path) -> PathBuf {
+    let mut name = OsString::from(path);
+    name.push(".dead");
+    PathBuf::from(name)
+}
+
+fn worker_path(path: &Path, worker: usize) -> PathBuf {
+    if worker == 0 {
+        return path.to_path_buf();
+    }
+    let mut name = OsString::from(path);
+    name.push(format!(".{}", worker));
+    PathBuf::from(name)
+}
+
+/// Reads the records left in the logs of every writer under `path` and gathers them in
+/// the first writer's log, which is returned with them. A record torn by a crash is
+/// cut off, so the next append starts on a frame boundary.
+pub(crate) fn recover(path: &Path) -> Result<(WriteAheadLog, Vec<Record>), LongtraceError> {
+    let (mut records, torn) = read(path)?;
+    // The writers of the last run may have been more than this one's
+    let mut others = Vec::new();
+    while worker_path(path, others.len() + 1).exists() {
+        let other = worker_path(path, others.len() + 1);
+        records.extend(read(&other)?.0);
+        others.push(other);
+    }
+
+    let mut log = WriteAheadLog::open(path, 0)?;
+    if let Some(valid_len) = torn.filter(|_| others.is_empty()) {
+        log.file.set_len(valid_len)?;
+        log.file.sync_data()?;
+    }
+    if !others.is_empty() {
+        log.truncate()?;
+        log.append(&records)?;
+        // A crash before this only duplicates records on the next replay
+        for other in others {
+            fs::remove_file(other)?;
+        }
+    }
+    Ok((log, records))
+}
+
+/// Opens the dead-letter log under `path` and reads the records a crash left in it,
+/// cutting off a torn one.
+pub(crate) fn recover_dead_letters(path: &Path) -> Result<(WriteAheadLog, Vec<Record>), LongtraceError> {
+    let path = dead_letter_path(path);
+    let (records, torn) = read(&path)?;
+    let log = WriteAheadLog::open_file(&path)?;
+    if let Some(valid_len) = torn {
+        log.file.set_len(valid_len)?;
+        log.file.sync_data()?;
+    }
+    Ok((log, records))
+}
+
+/// Reads the records of one log, along with the length of its whole frames when a
+/// torn one follows them.
+fn read(path: &Path) -> Result<(Vec<Record>, Option<u64>), LongtraceError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok((Vec::new(), None)),
+        Err(e) => return Err(e.into()),
+    };
+    let file_len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut records = Vec::new();
+    let mut valid_len = 0;
+    loop {
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        let mut json = Vec::with_capacity(len.min(1 << 20));
+        (&mut reader).take(len as u64).read_to_end(&mut json)?;
+        // A crash in the middle of an append leaves the last record cut short; it was
+        // never attempted, since appends are synced before the write
+        if json.len() < len {
+            break;
+        }
+        records.push(serde_json::from_slice(&json)?);
+        valid_len += 4 + len as u64;
+    }
+    Ok((records, (valid_len < file_len).then_some(valid_len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_recover() {
+        let path = std::env::temp_dir().join(format!("longtrace-{}.wal", Uuid::now_v7()));
+        let first: Vec<Record> = (0..3).map(|i| Record::builder().message(format!("first {}", i)).attr(serde_json::json!({"i": i})).build()).collect();
+        let second = vec![Record::builder().message("second").build()];
+
+        let mut log = WriteAheadLog::open(&path, 0).unwrap();
+        log.append(&first[..2]).unwrap();
+        log.truncate().unwrap();
+        log.append(&first).unwrap();
+        WriteAheadLog::open(&path, 1).unwrap().append(&second).unwrap();
+        drop(log);
+        // Cut short by a crash mid-append
+        let mut torn = OpenOptions::new().append(true).open(&path).unwrap();
+        torn.write_all(&100u32.to_le_bytes()).unwrap();
+        torn.write_all(b"{\"span_id\"").unwrap();
+        drop(torn);
+
+        let (mut log, records) = recover(&path).unwrap();
+        assert_eq!(records, [first.clone(), second].concat());
+        assert!(!worker_path(&path, 1).exists());
+        // Gathered in the first log, without the torn record
+        assert_eq!(read(&path).unwrap(), (records, None));
+
+        log.truncate().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        log.append(&first[..1]).unwrap();
+        drop(log);
+        assert_eq!(recover(&path).unwrap().1, first[..1]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recover_torn_single_log() {
+        let path = std::env::temp_dir().join(format!("longtrace-{}.wal", Uuid::now_v7()));
+        let first = vec![Record::builder().message("first").build()];
+        let second = vec![Record::builder().message("second").build()];
+
+        let mut log = WriteAheadLog::open(&path, 0).unwrap();
+        log.append(&first).unwrap();
+        drop(log);
+        // Even the length of the torn record is cut short
+        let mut torn = OpenOptions::new().append(true).open(&path).unwrap();
+        torn.write_all(&[7, 0]).unwrap();
+        drop(torn);
+
+        let (mut log, records) = recover(&path).unwrap();
+        assert_eq!(records, first);
+        assert_eq!(read(&path).unwrap(), (first.clone(), None));
+        // Appended after the last whole frame rather than after the torn one
+        log.append(&second).unwrap();
+        drop(log);
+        assert_eq!(recover(&path).unwrap().1, [first, second].concat());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_recover_dead_letters() {
+        let path = std::env::temp_dir().join(format!("longtrace-{}.wal", Uuid::now_v7()));
+        let failed = vec![Record::builder().message("failed").build()];
+
+        let (mut log, records) = recover_dead_letters(&path).unwrap();
+        assert!(records.is_empty());
+        log.append(&failed).unwrap();
+        drop(log);
+        let mut torn = OpenOptions::new().append(true).open(dead_letter_path(&path)).unwrap();
+        torn.write_all(&[3]).unwrap();
+        drop(torn);
+
+        // Not taken for a writer's log
+        assert!(recover(&path).unwrap().1.is_empty());
+        let (_, records) = recover_dead_letters(&path).unwrap();
+        assert_eq!(records, failed);
+        assert_eq!(read(&dead_letter_path(&path)).unwrap(), (failed, None));
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(dead_letter_path(&path)).unwrap();
+    }
+}
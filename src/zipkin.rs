@@ -0,0 +1,202 @@
+// --- Zipkin Export ---
+//
+// Writes records as a Zipkin v2 JSON array of spans, the body Zipkin's
+// `POST /api/v2/spans` accepts. A span's start and end records make one span, and the
+// events, logs and exceptions reported inside it become its annotations. Records
+// outside any exported span are left out.
+
+use crate::{LongtraceError, Record, EVENT, EXCEPTION, STATUS_ERROR, STATUS_OK};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::Path;
+use uuid::Uuid;
+
+struct ZipkinSpan {
+    trace_id: Uuid,
+    start: Record,
+    end: Option<Record>,
+    annotations: Vec<Value>,
+    exception: Option<Record>,
+}
+
+/// Writes the spans in `records`, oldest first, to a new file at `path` as Zipkin v2
+/// JSON, and returns how many spans were written.
+pub fn write_zipkin_json(path: &Path, service_name: &str, records: &[Record]) -> Result<u64, LongtraceError> {
+    let mut spans: Vec<ZipkinSpan> = Vec::new();
+    let mut index: HashMap<Uuid, usize> = HashMap::new();
+    for record in records {
+        match record.record_type {
+            1 => {
+                // The trace is named after the root ancestor, as in the OTLP exporter
+                let trace_id = match index.get(&record.parent_id) {
+                    Some(&parent) => spans[parent].trace_id,
+                    None if record.parent_id.is_nil() => record.span_id,
+                    None => record.parent_id,
+                };
+                index.insert(record.span_id, spans.len());
+                spans.push(ZipkinSpan { trace_id, start: record.clone(), end: None, annotations: Vec::new(), exception: None });
+            }
+            2 => {
+                if let Some(&span) = index.get(&record.span_id) {
+                    spans[span].end = Some(record.clone());
+                }
+            }
+            0 | EVENT | EXCEPTION => {
+                if let Some(&span) = index.get(&record.parent_id) {
+                    let span = &mut spans[span];
+                    span.annotations.push(json!({ "timestamp": unix_micros(record), "value": record.message }));
+                    if record.record_type == EXCEPTION {
+                        span.exception = Some(record.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let spans: Vec<Value> = spans.iter().map(|span| to_span(span, service_name)).collect();
+    let out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    serde_json::to_writer(out, &spans)?;
+    Ok(spans.len() as u64)
+}
+
+fn to_span(span: &ZipkinSpan, service_name: &str) -> Value {
+    let start = &span.start;
+    let mut tags = tags(start);
+    let mut value = json!({
+        "traceId": span.trace_id.simple().to_string(),
+        "id": span_id(start.span_id),
+        "name": start.message,
+        "timestamp": unix_micros(start),
+        "localEndpoint": { "serviceName": service_name },
+    });
+    if !start.parent_id.is_nil() {
+        value["parentId"] = json!(span_id(start.parent_id));
+    }
+    // Zipkin leaves out the duration of a span that has not finished
+    if let Some(end) = &span.end {
+        let duration = end.duration_ns.map_or_else(|| unix_micros(end) - unix_micros(start), |nanos| nanos / 1000);
+        // Zipkin rounds durations under a microsecond up to one
+        value["duration"] = json!(duration.max(1));
+        // An exception that left the span marks it as failed unless a status was set
+        if end.status == STATUS_ERROR || (span.exception.is_some() && end.status != STATUS_OK) {
+            let message = span.exception.as_ref().and_then(Record::attr_value).and_then(|attr| attr["exception_message"].as_str().map(str::to_string));
+            tags.insert("error".to_string(), json!(message.unwrap_or_else(|| "true".to_string())));
+        }
+    }
+    value["tags"] = Value::Object(tags);
+    if !span.annotations.is_empty() {
+        value["annotations"] = json!(span.annotations);
+    }
+    value
+}
+
+/// Zipkin span IDs are 64 bits; the low half of a v7 UUID is the random part.
+fn span_id(id: Uuid) -> String {
+    id.as_bytes()[8..].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Records without `timestamp_ns` only have microseconds
+fn unix_micros(record: &Record) -> i64 {
+    record.timestamp_ns.map_or_else(|| record.timestamp_utc().timestamp_micros(), |nanos| nanos / 1000)
+}
+
+/// Top-level `attr` keys; Zipkin tag values are strings, so others are kept as JSON text.
+fn tags(record: &Record) -> Map<String, Value> {
+    let Some(Value::Object(map)) = record.attr_value() else {
+        return Map::new();
+    };
+    map.into_iter()
+        .map(|(key, value)| match value {
+            Value::String(s) => (key, Value::String(s)),
+            other => (key, Value::String(other.to_string())),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The `ListOfSpans` definition of zipkin2-api.yaml
+    const SCHEMA: &str = r##"{
+        "type": "array",
+        "items": {
+            "type": "object",
+            "required": ["traceId", "id"],
+            "properties": {
+                "traceId": {"type": "string", "pattern": "^[a-f0-9]{16,32}$", "minLength": 16, "maxLength": 32},
+                "id": {"type": "string", "pattern": "^[a-f0-9]{16}$"},
+                "parentId": {"type": "string", "pattern": "^[a-f0-9]{16}$"},
+                "name": {"type": "string"},
+                "kind": {"enum": ["CLIENT", "SERVER", "PRODUCER", "CONSUMER"]},
+                "timestamp": {"type": "integer"},
+                "duration": {"type": "integer", "minimum": 1},
+                "debug": {"type": "boolean"},
+                "shared": {"type": "boolean"},
+                "localEndpoint": {"$ref": "#/$defs/Endpoint"},
+                "remoteEndpoint": {"$ref": "#/$defs/Endpoint"},
+                "annotations": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {"timestamp": {"type": "integer"}, "value": {"type": "string"}}
+                    }
+                },
+                "tags": {"type": "object", "additionalProperties": {"type": "string"}}
+            }
+        },
+        "$defs": {
+            "Endpoint": {
+                "type": "object",
+                "properties": {
+                    "serviceName": {"type": "string"},
+                    "ipv4": {"type": "string"},
+                    "ipv6": {"type": "string"},
+                    "port": {"type": "integer"}
+                }
+            }
+        }
+    }"##;
+
+    #[test]
+    fn test_write_zipkin_json() {
+        let root = Uuid::now_v7();
+        let child = Uuid::now_v7();
+        let grandchild = Uuid::now_v7();
+        let records = vec![
+            Record::builder().span_id(root).record_type(1).message("request").attr(json!({"path": "/", "attempt": 1})).build(),
+            Record::builder().span_id(child).parent_id(root).record_type(1).message("query").build(),
+            Record::builder().span_id(grandchild).parent_id(child).record_type(1).message("connect").build(),
+            Record::builder().parent_id(grandchild).record_type(EVENT).message("retrying").build(),
+            Record::builder().span_id(grandchild).parent_id(child).record_type(2).duration_ns(400).build(),
+            Record::builder().parent_id(child).record_type(EXCEPTION).attr(json!({"exception_message": "timed out"})).build(),
+            Record::builder().span_id(child).parent_id(root).record_type(2).duration_ns(3_000).build(),
+            Record::builder().span_id(root).record_type(2).status(STATUS_OK).duration_ns(5_000).build(),
+        ];
+
+        let path = std::env::temp_dir().join(format!("longtrace-{}.zipkin.json", Uuid::now_v7()));
+        assert_eq!(write_zipkin_json(&path, "checkout", &records).unwrap(), 3);
+        let spans: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        let validator = jsonschema::validator_for(&schema).unwrap();
+        let errors: Vec<String> = validator.iter_errors(&spans).map(|e| e.to_string()).collect();
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let trace_id = root.simple().to_string();
+        assert!(spans.as_array().unwrap().iter().all(|span| span["traceId"] == trace_id));
+        assert_eq!(spans[0]["parentId"], Value::Null);
+        assert_eq!(spans[0]["tags"], json!({"path": "/", "attempt": "1"}));
+        assert_eq!(spans[0]["duration"], 5);
+        assert_eq!(spans[0]["localEndpoint"]["serviceName"], "checkout");
+        assert_eq!(spans[1]["parentId"], span_id(root));
+        assert_eq!(spans[1]["tags"]["error"], "timed out");
+        assert_eq!(spans[2]["parentId"], span_id(child));
+        assert_eq!(spans[2]["duration"], 1);
+        assert_eq!(spans[2]["annotations"][0]["value"], "retrying");
+
+        // The schema does catch a malformed span
+        assert!(!validator.is_valid(&json!([{"traceId": "xyz", "id": span_id(root)}])));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
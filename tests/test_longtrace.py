@@ -3,8 +3,11 @@ import threading
 import json
 import uuid
 import time
+import datetime
 import os
 import sys
+import asyncio
+import inspect
 
 # 尝试导入 longtrace
 # 注意：在运行此测试前，请确保已编译并安装扩展，或者当前目录下有 longtrace.so
@@ -20,6 +23,12 @@ except ImportError:
 # 优先使用 DATABASE_URL (devcontainer 标准)，其次是 LONGTRACE_DB_URL，最后是默认值
 CONN_STR = os.environ.get("DATABASE_URL") or os.environ.get("LONGTRACE_DB_URL", "host=localhost user=postgres password=secret")
 
+def log_in_child(parent_id):
+    """在 fork 出的子进程中写一条记录，供 multiprocessing 调用"""
+    longtrace.Tracer(parent_id=parent_id, registry="forked").log(f"child {os.getpid()}")
+    longtrace.flush("forked")
+    return os.getpid()
+
 class TestLongtrace(unittest.TestCase):
     @classmethod
     def setUpClass(cls):
@@ -197,5 +206,1676 @@ class TestLongtrace(unittest.TestCase):
             else:
                 raise e
 
+    def test_08_channel_full_error(self):
+        """测试 ChannelFullError 可以被单独捕获"""
+        self.assertTrue(issubclass(longtrace.ChannelFullError, RuntimeError))
+
+    def test_09_named_registries(self):
+        """测试多个命名 registry 可以同时存在并独立写入"""
+        try:
+            # 不传 candidate_name，写入按日期自动创建的数据库，与 default registry 相互独立
+            longtrace.initialize(CONN_STR, batch_size=10, name="secondary")
+        except RuntimeError as e:
+            print(f"Skipping named registry test due to DB error: {e}")
+            return
+
+        try:
+            with self.assertRaises(RuntimeError) as cm:
+                longtrace.initialize(CONN_STR, name="secondary")
+            self.assertIn("already initialized", str(cm.exception))
+
+            default_tracer = longtrace.Tracer()
+            secondary_tracer = longtrace.Tracer(registry="secondary")
+
+            def worker(tracer, name):
+                for i in range(20):
+                    tracer.log(f"{name} {i}")
+
+            threads = [
+                threading.Thread(target=worker, args=(default_tracer, "default")),
+                threading.Thread(target=worker, args=(secondary_tracer, "secondary")),
+            ]
+            for t in threads:
+                t.start()
+            for t in threads:
+                t.join()
+
+            # 单次 log 也可以指定 registry
+            default_tracer.log("Routed to secondary", registry="secondary")
+            longtrace.flush(registry="secondary")
+        finally:
+            longtrace.shutdown("secondary")
+
+        # shutdown 之后该 registry 不再可用
+        with self.assertRaises(RuntimeError) as cm:
+            longtrace.Tracer(registry="secondary").log("After shutdown")
+        self.assertIn("Database not initialized", str(cm.exception))
+
+    def test_10_force_reinitialize(self):
+        """测试 force=True 可以替换已有的数据库"""
+        tracer = longtrace.Tracer()
+        try:
+            tracer.log("Before reinitialize")
+            name = longtrace.initialize(CONN_STR, batch_size=10, candidate_name="longtrace", force=True)
+        except RuntimeError as e:
+            print(f"Skipping force reinitialize test due to DB error: {e}")
+            return
+
+        self.assertEqual(name, "longtrace")
+        # 已有的 Tracer 会写入新的数据库
+        tracer.log("After reinitialize")
+        longtrace.flush()
+
+        # 不带 force 仍然报错
+        with self.assertRaises(RuntimeError) as cm:
+            longtrace.initialize(CONN_STR)
+        self.assertIn("already initialized", str(cm.exception))
+
+    def test_11_shutdown(self):
+        """测试显式 shutdown 之后可以重新初始化"""
+        tracer = longtrace.Tracer()
+        try:
+            tracer.log("Before shutdown")
+        except RuntimeError as e:
+            print(f"Skipping shutdown test due to DB error: {e}")
+            return
+
+        longtrace.shutdown()
+        with self.assertRaises(RuntimeError) as cm:
+            tracer.log("After shutdown")
+        self.assertIn("Database not initialized", str(cm.exception))
+
+        # 重复 shutdown 不报错
+        longtrace.shutdown()
+
+        longtrace.initialize(CONN_STR, batch_size=10, candidate_name="longtrace")
+        tracer.log("After re-initialize")
+
+    def test_12_span_id(self):
+        """测试 with ... as 可以拿到 span_id"""
+        tracer = longtrace.Tracer()
+        guard = tracer.span("Span with id")
+        try:
+            with guard as span_id:
+                headers = {"x-trace-id": span_id}
+        except RuntimeError as e:
+            print(f"Skipping span id test due to DB error: {e}")
+            return
+
+        self.assertEqual(str(uuid.UUID(headers["x-trace-id"])), span_id)
+        # 在 with 语句之外也可以访问
+        self.assertEqual(guard.span_id, span_id)
+
+    def test_13_span_events(self):
+        """测试 span 内的 add_event"""
+        self.assertEqual(longtrace.EVENT, 3)
+        tracer = longtrace.Tracer()
+        try:
+            guard = tracer.span("Span with events")
+            with guard:
+                guard.add_event("Cache miss", json.dumps({"key": "user:1"}))
+                guard.add_event("Retry scheduled")
+        except RuntimeError as e:
+            print(f"Skipping span events test due to DB error: {e}")
+
+    def test_14_span_exception(self):
+        """测试 span 内抛出的异常会被记录并继续向外传播"""
+        self.assertEqual(longtrace.EXCEPTION, 4)
+        tracer = longtrace.Tracer()
+        try:
+            tracer.log("Before exception")
+        except RuntimeError as e:
+            print(f"Skipping span exception test due to DB error: {e}")
+            return
+
+        with self.assertRaises(ValueError):
+            with tracer.span("Failing span"):
+                raise ValueError("boom")
+
+        # 异常之后 span 栈已经弹出，新的 span 正常工作
+        with tracer.span("After exception"):
+            pass
+
+    def test_15_sample_rate(self):
+        """测试 Tracer 的 sample_rate 参数"""
+        with self.assertRaises(ValueError):
+            longtrace.Tracer(sample_rate=1.5)
+
+        tracer = longtrace.Tracer(sample_rate=0.0)
+        # 采样率为 0 时不会写入任何记录，因此即使数据库不可用也不会报错
+        tracer.log("Never recorded")
+        with tracer.span("Never recorded span") as span_id:
+            tracer.log("Never recorded child")
+        self.assertIsInstance(span_id, str)
+
+    def test_16_max_depth(self):
+        """测试 span 嵌套深度上限和 stack_depth"""
+        # 采样率为 0 时 span 不写入数据库，但仍然计入嵌套深度
+        tracer = longtrace.Tracer(sample_rate=0.0, max_depth=2)
+        self.assertEqual(tracer.stack_depth(), 0)
+        with tracer.span("Level 1"):
+            with tracer.span("Level 2"):
+                self.assertEqual(tracer.stack_depth(), 2)
+                with self.assertRaises(RuntimeError) as cm:
+                    with tracer.span("Level 3"):
+                        pass
+                self.assertIn("Maximum span depth", str(cm.exception))
+                self.assertEqual(tracer.stack_depth(), 2)
+        self.assertEqual(tracer.stack_depth(), 0)
+
+    def test_17_query_span(self):
+        """测试 query_span 按 span_id 查询记录"""
+        tracer = longtrace.Tracer()
+        try:
+            with tracer.span("Queried span", json.dumps({"step": "start"})) as span_id:
+                pass
+            longtrace.flush()
+        except RuntimeError as e:
+            print(f"Skipping query span test due to DB error: {e}")
+            return
+
+        records = longtrace.query_span(span_id)
+        # span 的开始和结束记录共用同一个 span_id
+        self.assertEqual([r["record_type"] for r in records], [1, 2])
+        self.assertEqual(records[0]["span_id"], span_id)
+        self.assertEqual(records[0]["message"], "Queried span")
+        self.assertEqual(records[0]["attr"]["step"], "start")
+        self.assertLessEqual(records[0]["timestamp"], records[1]["timestamp"])
+
+        self.assertEqual(len(longtrace.query_span(span_id, limit=1)), 1)
+        with self.assertRaises(ValueError):
+            longtrace.query_span("invalid-uuid")
+
+    def test_18_get_trace(self):
+        """测试 get_trace 返回整棵 trace 树"""
+        root_parent_id = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root_parent_id)
+        try:
+            with tracer.span("Level 1"):
+                with tracer.span("Level 2"):
+                    with tracer.span("Level 3"):
+                        tracer.log("Leaf")
+            longtrace.flush()
+        except RuntimeError as e:
+            print(f"Skipping get trace test due to DB error: {e}")
+            return
+
+        records = longtrace.get_trace(root_parent_id)
+        self.assertEqual(
+            [r["message"] for r in records],
+            ["Level 1", "Level 2", "Level 3", "Leaf", "Level 3", "Level 2", "Level 1"],
+        )
+
+    def test_19_query_range(self):
+        """测试 query_range 按时间范围查询记录"""
+        tracer = longtrace.Tracer()
+        start = time.time()
+        try:
+            tracer.log("Range log", json.dumps({"marker": "range"}))
+            longtrace.flush()
+        except RuntimeError as e:
+            print(f"Skipping query range test due to DB error: {e}")
+            return
+        end = time.time()
+
+        records = longtrace.query_range(start, end, record_type=0)
+        self.assertTrue(any(r["attr"].get("marker") == "range" for r in records))
+        self.assertTrue(all(r["record_type"] == 0 for r in records))
+
+        self.assertLessEqual(len(longtrace.query_range(start - 3600, end, limit=1)), 1)
+
+    def test_20_severity(self):
+        """测试 log 的 level 参数和 debug/info/warn/error 方法"""
+        self.assertEqual(
+            [longtrace.DEBUG, longtrace.INFO, longtrace.WARN, longtrace.ERROR, longtrace.FATAL],
+            [0, 1, 2, 3, 4],
+        )
+        tracer = longtrace.Tracer()
+        with self.assertRaises(ValueError):
+            tracer.log("Bad level", level=9)
+
+        try:
+            with tracer.span("Severity span") as span_id:
+                tracer.debug("Debug")
+                tracer.info("Info")
+                tracer.warn("Warn", json.dumps({"retry": 1}))
+                tracer.error("Error")
+                tracer.log("Fatal", level=longtrace.FATAL)
+            longtrace.flush()
+        except RuntimeError as e:
+            print(f"Skipping severity test due to DB error: {e}")
+            return
+
+        children = [r for r in longtrace.get_trace(span_id) if r["record_type"] == 0]
+        self.assertEqual([r["severity"] for r in children], [0, 1, 2, 3, 4])
+
+    def test_21_metadata(self):
+        """测试 attr 中自动加入 host/pid/thread 元数据"""
+        tracer = longtrace.Tracer()
+        try:
+            with tracer.span("Metadata span") as span_id:
+                tracer.log("User pid", json.dumps({"_pid": "user value"}))
+                tracer.log("Without attr")
+            longtrace.flush()
+        except RuntimeError as e:
+            print(f"Skipping metadata test due to DB error: {e}")
+            return
+
+        user_log, plain_log = [r for r in longtrace.get_trace(span_id) if r["record_type"] == 0]
+        # 用户提供的 _ 开头的键不会被覆盖
+        self.assertEqual(user_log["attr"]["_pid"], "user value")
+        self.assertEqual(plain_log["attr"]["_pid"], os.getpid())
+        self.assertIn("_host", plain_log["attr"])
+        self.assertIn("_thread", plain_log["attr"])
+
+    def test_22_partitioning_option(self):
+        """测试 partitioning 参数只接受 daily/weekly"""
+        with self.assertRaises(ValueError):
+            longtrace.initialize(CONN_STR, partitioning="hourly", name="partitioned")
+
+    def test_23_cleanup(self):
+        """测试 cleanup 删除旧记录"""
+        with self.assertRaises(ValueError):
+            longtrace.cleanup(-1)
+
+        try:
+            # 一百年前的记录不存在，因此不会删除任何记录
+            deleted = longtrace.cleanup(100 * 365 * 24 * 3600)
+        except RuntimeError as e:
+            print(f"Skipping cleanup test due to DB error: {e}")
+            return
+        self.assertEqual(deleted, 0)
+
+    def test_24_sqlite_backend(self):
+        """测试以 .db 结尾的路径使用 SQLite 后端"""
+        import tempfile
+        path = os.path.join(tempfile.mkdtemp(), "trace.db")
+        self.assertEqual(longtrace.initialize(path, name="sqlite"), path)
+        try:
+            tracer = longtrace.Tracer(registry="sqlite")
+            with tracer.span("SQLite span") as span_id:
+                tracer.log("SQLite log", json.dumps({"key": "value"}))
+            longtrace.flush(registry="sqlite")
+
+            records = longtrace.get_trace(span_id, registry="sqlite")
+            self.assertEqual([r["message"] for r in records], ["SQLite log"])
+            self.assertEqual(records[0]["attr"]["key"], "value")
+            self.assertEqual(len(longtrace.query_span(span_id, registry="sqlite")), 2)
+        finally:
+            longtrace.shutdown("sqlite")
+
+    def test_25_trace_context(self):
+        """测试 traceparent 的 inject/extract"""
+        span_id = str(uuid.uuid4())
+        header = longtrace.inject_context(span_id)
+        # 标准的 parent-id 只有 16 位，取 UUID 的低 64 位
+        self.assertEqual(header, f"00-{uuid.UUID(span_id).hex}-{uuid.UUID(span_id).hex[16:]}-01")
+        low_bits = str(uuid.UUID(int=uuid.UUID(span_id).int & (2**64 - 1)))
+        self.assertEqual(longtrace.extract_context(header), (span_id, low_bits))
+        # 完整的 UUID 通过 tracestate 传递
+        tracestate = longtrace.inject_tracestate(span_id)
+        self.assertEqual(tracestate, f"longtrace={uuid.UUID(span_id).hex}")
+        self.assertEqual(longtrace.extract_context(header, tracestate), (span_id, span_id))
+
+        parent_id = str(uuid.uuid4())
+        header = longtrace.inject_context(span_id, parent_id)
+        tracestate = "other=1," + longtrace.inject_tracestate(span_id, parent_id)
+        self.assertEqual(longtrace.extract_context(header, tracestate), (span_id, parent_id))
+
+        with self.assertRaises(ValueError):
+            longtrace.extract_context("not a header")
+
+        # 使用提取出的 parent_id 创建 Tracer，继续远端的 trace
+        _, remote_parent = longtrace.extract_context(header)
+        tracer = longtrace.Tracer(parent_id=remote_parent)
+        try:
+            with tracer.span("Remote child"):
+                pass
+            longtrace.flush()
+        except RuntimeError as e:
+            print(f"Skipping trace context test due to DB error: {e}")
+            return
+        self.assertEqual([r["message"] for r in longtrace.get_trace(remote_parent)], ["Remote child", "Remote child"])
+
+    def test_26_file_backend(self):
+        """测试 file:// 连接串写入 JSON Lines 文件"""
+        import tempfile
+        path = os.path.join(tempfile.mkdtemp(), "trace.ndjson")
+        self.assertEqual(longtrace.initialize(f"file://{path}?rotate=daily", name="file"), path)
+        try:
+            tracer = longtrace.Tracer(registry="file")
+            with tracer.span("File span") as span_id:
+                tracer.log("File log", json.dumps({"key": "value"}))
+            longtrace.flush(registry="file")
+
+            with open(path) as f:
+                lines = [json.loads(line) for line in f]
+            self.assertEqual([line["record_type"] for line in lines], [1, 0, 2])
+            self.assertEqual(lines[1]["attr"]["key"], "value")
+            self.assertEqual([r["message"] for r in longtrace.get_trace(span_id, registry="file")], ["File log"])
+        finally:
+            longtrace.shutdown("file")
+
+        with self.assertRaises(RuntimeError):
+            longtrace.initialize(f"file://{path}?rotate=hourly", name="file")
+
+    def test_27_export_parquet(self):
+        """测试导出 Parquet 文件"""
+        import tempfile
+        tracer = longtrace.Tracer()
+        start = time.time()
+        with tracer.span("Parquet span") as span_id:
+            for i in range(10):
+                tracer.log(f"Parquet log {i}")
+        longtrace.flush()
+
+        path = os.path.join(tempfile.mkdtemp(), "trace.parquet")
+        self.assertEqual(longtrace.export_parquet(path, start=start - 1, span_id_prefix=span_id), 2)
+        with open(path, "rb") as f:
+            self.assertEqual(f.read(4), b"PAR1")
+
+    def test_28_stats(self):
+        """测试连接池与写入线程统计"""
+        pool = longtrace.pool_stats()
+        self.assertEqual(set(pool), {"connections", "idle", "pending"})
+        self.assertGreaterEqual(pool["connections"], 1)
+
+        before = longtrace.writer_stats()
+        tracer = longtrace.Tracer()
+        for i in range(5):
+            tracer.log(f"Stats log {i}")
+        longtrace.flush()
+        after = longtrace.writer_stats()
+        self.assertEqual(after["pending"], 0)
+        self.assertEqual(after["total_flushed"] - before["total_flushed"], 5)
+        self.assertEqual(after["total_failed"], before["total_failed"])
+
+    def test_29_metrics(self):
+        """测试写入指标在 flush 后递增"""
+        before = longtrace.metrics()
+        self.assertEqual(set(before), {"records_inserted", "records_failed", "flush_count", "mean_flush_ms"})
+        tracer = longtrace.Tracer()
+        for i in range(3):
+            tracer.log(f"Metrics log {i}")
+        longtrace.flush()
+        after = longtrace.metrics()
+        self.assertEqual(after["records_inserted"] - before["records_inserted"], 3)
+        self.assertEqual(after["flush_count"] - before["flush_count"], 1)
+        self.assertGreater(after["mean_flush_ms"], 0)
+
+    def test_30_circuit_state(self):
+        """测试熔断器状态"""
+        self.assertEqual(longtrace.get_circuit_state(), "closed")
+        with self.assertRaises(RuntimeError):
+            longtrace.initialize(CONN_STR, failure_threshold=0, name="circuit")
+
+    def test_31_dead_letters(self):
+        """测试写入失败的记录进入死信队列"""
+        longtrace.drain_dead_letters()
+        tracer = longtrace.Tracer()
+        # PostgreSQL 拒绝包含 NUL 字节的文本
+        tracer.log("Dead letter\0")
+        longtrace.flush()
+        self.assertEqual(longtrace.dead_letter_count(), 1)
+        records = longtrace.drain_dead_letters()
+        self.assertEqual([r["message"] for r in records], ["Dead letter\0"])
+        self.assertEqual(longtrace.dead_letter_count(), 0)
+
+    def test_32_begin_span(self):
+        """测试 begin_span/finish 与 with span() 写入相同的记录"""
+        def shape(root):
+            longtrace.flush()
+            records = longtrace.get_trace(root)
+            ids = {root: "root"}
+            for r in records:
+                if r["record_type"] == 1:
+                    ids[r["span_id"]] = r["message"]
+            return [(r["record_type"], r["message"], ids.get(r["parent_id"])) for r in records]
+
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        with tracer.span("Outer"):
+            with tracer.span("Inner"):
+                tracer.log("Inside")
+        expected = shape(root)
+
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        outer = tracer.begin_span("Outer")
+        inner = tracer.begin_span("Inner")
+        self.assertEqual(tracer.stack_depth(), 2)
+        tracer.log("Inside")
+        inner.finish()
+        outer.finish()
+        self.assertEqual(tracer.stack_depth(), 0)
+        self.assertEqual(shape(root), expected)
+
+        with self.assertRaises(RuntimeError):
+            outer.finish()
+
+    def test_33_span_links(self):
+        """测试 link_to 在两个独立的 trace 之间写入 LINK 记录"""
+        producer_root = str(uuid.uuid4())
+        consumer_root = str(uuid.uuid4())
+        producer = longtrace.Tracer(parent_id=producer_root)
+        consumer = longtrace.Tracer(parent_id=consumer_root)
+        with producer.span("Produce") as producer_span_id:
+            pass
+        guard = consumer.span("Consume")
+        with guard as consumer_span_id:
+            guard.link_to(producer_span_id)
+        longtrace.flush()
+
+        links = [r for r in longtrace.get_trace(consumer_root) if r["record_type"] == longtrace.LINK]
+        self.assertEqual(len(links), 1)
+        self.assertEqual(links[0]["span_id"], consumer_span_id)
+        self.assertEqual(links[0]["parent_id"], consumer_span_id)
+        self.assertEqual(links[0]["linked_span_id"], producer_span_id)
+        self.assertEqual(links[0]["attr"]["linked_span_id"], producer_span_id)
+        # The producer's trace is untouched
+        producer_records = longtrace.get_trace(producer_root)
+        self.assertTrue(all(r["record_type"] != longtrace.LINK for r in producer_records))
+
+        with self.assertRaises(ValueError):
+            guard.link_to("not-a-uuid")
+
+    def test_34_span_status(self):
+        """测试 set_status 的状态写入 span 结束记录"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        failed = tracer.span("Failed job")
+        with failed as failed_id:
+            failed.set_status(longtrace.STATUS_ERROR)
+        with tracer.span("Plain job") as plain_id:
+            pass
+        with self.assertRaises(ValueError):
+            with tracer.span("Crashed job") as crashed_id:
+                raise ValueError("boom")
+        with self.assertRaises(ValueError):
+            failed.set_status(3)
+        longtrace.flush()
+
+        records = longtrace.get_trace(root)
+        ends = {r["span_id"]: r["status"] for r in records if r["record_type"] == 2}
+        self.assertEqual(ends[failed_id], longtrace.STATUS_ERROR)
+        self.assertEqual(ends[plain_id], longtrace.STATUS_UNSET)
+        # An exception fails a span whose status was never set
+        self.assertEqual(ends[crashed_id], longtrace.STATUS_ERROR)
+        updates = [r for r in records if r["record_type"] == longtrace.STATUS_UPDATE]
+        self.assertEqual([(u["parent_id"], u["status"]) for u in updates], [(failed_id, 2)])
+
+    def test_35_async_tracer(self):
+        """测试 AsyncTracer 中并发的协程各自拥有独立的 span 栈"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.AsyncTracer(parent_id=root)
+        seen = {}
+
+        async def task(name):
+            async with tracer.span(name) as span_id:
+                await asyncio.sleep(0.01)
+                async with tracer.span(name + " child") as child_id:
+                    await asyncio.sleep(0.01)
+                    tracer.log(name + " log")
+                    seen[name] = (span_id, child_id, tracer.get_current_span_id())
+                self.assertEqual(tracer.get_current_span_id(), span_id)
+
+        async def main():
+            await asyncio.gather(task("A"), task("B"))
+            self.assertIsNone(tracer.get_current_span_id())
+
+        asyncio.run(main())
+        longtrace.flush()
+
+        records = longtrace.get_trace(root)
+        parents = {(r["record_type"], r["message"]): r["parent_id"] for r in records}
+        for name, (span_id, child_id, current) in seen.items():
+            self.assertEqual(current, child_id)
+            self.assertEqual(parents[(1, name)], root)
+            self.assertEqual(parents[(1, name + " child")], span_id)
+            self.assertEqual(parents[(0, name + " log")], child_id)
+            self.assertEqual(parents[(2, name + " child")], span_id)
+        self.assertEqual(len(records), 2 * 5)
+
+    def test_36_span_context(self):
+        """测试 with span() 通过 contextvars 在并发协程之间隔离"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        seen = {}
+
+        async def task(name):
+            with tracer.span(name) as span_id:
+                await asyncio.sleep(0.01)
+                tracer.log(name + " log")
+                seen[name] = (span_id, tracer.get_current_span_id())
+
+        async def main():
+            await asyncio.gather(task("A"), task("B"))
+
+        asyncio.run(main())
+        self.assertIsNone(tracer.get_current_span_id())
+        longtrace.flush()
+
+        parents = {(r["record_type"], r["message"]): r["parent_id"] for r in longtrace.get_trace(root)}
+        for name, (span_id, current) in seen.items():
+            self.assertEqual(current, span_id)
+            self.assertEqual(parents[(1, name)], root)
+            self.assertEqual(parents[(0, name + " log")], span_id)
+
+        # push_context makes a span from elsewhere the parent until it is popped
+        remote = str(uuid.uuid4())
+        token = tracer.push_context(remote)
+        self.assertEqual(tracer.get_current_span_id(), remote)
+        with tracer.span("Under remote") as span_id:
+            pass
+        tracer.pop_context(token)
+        self.assertIsNone(tracer.get_current_span_id())
+        longtrace.flush()
+        self.assertEqual(longtrace.query_span(span_id)[0]["parent_id"], remote)
+
+    def test_37_get_tracer(self):
+        """测试 get_tracer 按名字在多个线程中返回同一个 Tracer"""
+        root = str(uuid.uuid4())
+        tracers = []
+        barrier = threading.Barrier(4)
+
+        def worker():
+            barrier.wait()
+            tracers.append(longtrace.get_tracer("test_37", parent_id=root))
+
+        threads = [threading.Thread(target=worker) for _ in range(4)]
+        for t in threads:
+            t.start()
+        for t in threads:
+            t.join()
+        self.assertEqual(len(tracers), 4)
+        self.assertTrue(all(t is tracers[0] for t in tracers))
+
+        # Later arguments are ignored for an existing name
+        tracer = longtrace.get_tracer("test_37", parent_id=str(uuid.uuid4()), sample_rate=0.0)
+        self.assertIs(tracer, tracers[0])
+        with tracer.span("Registered") as span_id:
+            pass
+        longtrace.flush()
+        self.assertEqual(longtrace.query_span(span_id)[0]["parent_id"], root)
+
+        self.assertTrue(longtrace.remove_tracer("test_37"))
+        self.assertFalse(longtrace.remove_tracer("test_37"))
+        self.assertIsNot(longtrace.get_tracer("test_37"), tracer)
+        longtrace.remove_tracer("test_37")
+
+    def test_38_tracer_fork(self):
+        """测试 fork() 得到的 Tracer 嵌套在当前 span 之下"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        with tracer.span("Parent") as parent_id:
+            forked = tracer.fork()
+            self.assertEqual(forked.stack_depth(), 0)
+
+        def worker():
+            with forked.span("Child"):
+                forked.log("In child")
+
+        t = threading.Thread(target=worker)
+        t.start()
+        t.join()
+        longtrace.flush()
+
+        records = longtrace.get_trace(root)
+        parents = {(r["record_type"], r["message"]): r["parent_id"] for r in records}
+        self.assertEqual(parents[(1, "Parent")], root)
+        child_id = [r["span_id"] for r in records if r["message"] == "Child"][0]
+        self.assertEqual(parents[(1, "Child")], parent_id)
+        self.assertEqual(parents[(0, "In child")], child_id)
+
+    def test_39_trace_decorator(self):
+        """测试 @tracer.trace 为同步和异步函数创建 span"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+
+        @tracer.trace
+        def add(a, b=1):
+            """Adds numbers."""
+            tracer.log("Adding")
+            return a + b
+
+        @tracer.trace(attr_extractor=lambda args, kwargs: {"url": args[0]})
+        async def fetch(url):
+            await asyncio.sleep(0)
+            return url.upper()
+
+        self.assertEqual(add(2, b=3), 5)
+        self.assertEqual(asyncio.run(fetch("a")), "A")
+        with self.assertRaises(TypeError):
+            add()
+
+        # functools.wraps keeps the function's metadata
+        self.assertEqual(add.__name__, "add")
+        self.assertEqual(add.__doc__, "Adds numbers.")
+        self.assertEqual(str(inspect.signature(add)), "(a, b=1)")
+        self.assertTrue(inspect.iscoroutinefunction(fetch))
+        longtrace.flush()
+
+        records = longtrace.get_trace(root)
+        starts = {}
+        for r in records:
+            if r["record_type"] == 1:
+                starts.setdefault(r["message"], r)
+        self.assertEqual(set(starts), {"add", "fetch"})
+        self.assertEqual(starts["fetch"]["attr"]["url"], "a")
+        log = [r for r in records if r["message"] == "Adding"]
+        self.assertEqual([r["parent_id"] for r in log], [starts["add"]["span_id"]])
+        # The failed call is recorded as an exception in its own span
+        self.assertEqual(sum(r["record_type"] == longtrace.EXCEPTION for r in records), 1)
+
+    def test_40_set_attr(self):
+        """测试在 span 进行中设置的属性写入 span 结束记录"""
+        span = longtrace.Tracer().span("Request", json.dumps({"path": "/"}))
+        with span as span_id:
+            span.set_attr("status_code", 200)
+            span.set_attrs({"bytes": 512, "tags": ["a", "b"]})
+            self.assertEqual(span.get_attr("status_code"), 200)
+            self.assertEqual(span.get_attr("tags"), ["a", "b"])
+            self.assertIsNone(span.get_attr("missing"))
+            with self.assertRaises(TypeError):
+                span.set_attr("bad", object())
+        longtrace.flush()
+
+        records = longtrace.query_span(span_id)
+        start, end = records[0], records[-1]
+        self.assertEqual(end["record_type"], 2)
+        self.assertEqual(end["attr"]["status_code"], 200)
+        self.assertEqual(end["attr"]["bytes"], 512)
+        self.assertEqual(end["attr"]["path"], "/")
+        self.assertNotIn("status_code", start["attr"])
+
+    def test_41_report_batch(self):
+        """测试 report_batch 一次写入 1000 条记录，且比逐条写入更快"""
+        root = str(uuid.uuid4())
+        span_id = str(uuid.uuid4())
+        records = [(f"Batched {i}", span_id, root, json.dumps({"i": i}), 0) for i in range(1000)]
+        start = time.perf_counter()
+        longtrace.report_batch(records)
+        batch_time = time.perf_counter() - start
+        longtrace.flush()
+
+        stored = longtrace.query_span(span_id, limit=2000)
+        self.assertEqual(len(stored), 1000)
+        self.assertEqual(stored[0]["parent_id"], root)
+        self.assertEqual(stored[0]["attr"]["i"], 0)
+
+        tracer = longtrace.Tracer(parent_id=root)
+        start = time.perf_counter()
+        for i in range(1000):
+            tracer.log(f"Single {i}")
+        single_time = time.perf_counter() - start
+        longtrace.flush()
+        self.assertLess(batch_time, single_time)
+
+        with self.assertRaises(ValueError):
+            longtrace.report_batch([("Bad", "not-a-uuid", root, None, 0)])
+
+    def test_42_flush_sync(self):
+        """测试 flush_sync 在超时内写入记录，非法超时抛出 ValueError"""
+        span_id = str(uuid.uuid4())
+        longtrace.report_batch([("Flushed synchronously", span_id, str(uuid.uuid4()), None, 0)])
+        longtrace.flush_sync(timeout_seconds=10.0)
+        self.assertEqual(len(longtrace.query_span(span_id)), 1)
+
+        with self.assertRaises(ValueError):
+            longtrace.flush_sync(timeout_seconds=-1.0)
+        # 未初始化的 registry 直接返回
+        longtrace.flush_sync(registry="never-initialized")
+
+    def test_43_is_initialized(self):
+        """测试 is_initialized 和 db_name 反映 registry 的初始化状态"""
+        self.assertTrue(longtrace.is_initialized())
+        self.assertEqual(longtrace.db_name(), "longtrace")
+
+        self.assertFalse(longtrace.is_initialized("guarded"))
+        self.assertIsNone(longtrace.db_name("guarded"))
+        longtrace.initialize(CONN_STR, candidate_name="longtrace", name="guarded")
+        try:
+            self.assertTrue(longtrace.is_initialized("guarded"))
+            self.assertEqual(longtrace.db_name("guarded"), "longtrace")
+        finally:
+            longtrace.shutdown("guarded")
+        self.assertFalse(longtrace.is_initialized("guarded"))
+        self.assertIsNone(longtrace.db_name("guarded"))
+
+    @unittest.skipUnless(hasattr(longtrace, "reset"), "requires the test-utils feature")
+    def test_44_reset(self):
+        """测试 reset 写入待处理记录后清空所有 registry，之后可以重新初始化"""
+        span_id = str(uuid.uuid4())
+        longtrace.initialize(CONN_STR, batch_size=1000, candidate_name="longtrace", name="resettable")
+        longtrace.report_batch([("Pending before reset", span_id, str(uuid.uuid4()), None, 0)], registry="resettable")
+        tracer = longtrace.get_tracer("resettable")
+
+        longtrace.reset()
+        self.assertFalse(longtrace.is_initialized())
+        self.assertFalse(longtrace.is_initialized("resettable"))
+        self.assertIsNot(longtrace.get_tracer("resettable"), tracer)
+
+        # 恢复其他测试使用的 default registry
+        longtrace.initialize(CONN_STR, batch_size=10, candidate_name="longtrace")
+        # reset 之前的记录已经写入
+        self.assertEqual(len(longtrace.query_span(span_id)), 1)
+
+    def test_45_ambient_attrs(self):
+        """测试 attrs 上下文为其中的 log 和 span 添加属性，嵌套时逐层合并"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        with longtrace.attrs({"request_id": "r1", "layer": "outer"}):
+            tracer.log("Outer")
+            with longtrace.attrs({"layer": "inner", "user": 42}) as ctx:
+                self.assertIsInstance(ctx, longtrace.AttrContext)
+                tracer.log("Inner", attr=json.dumps({"user": 7}))
+                with tracer.span("Inner span"):
+                    pass
+            tracer.log("Outer again")
+        tracer.log("Outside")
+        longtrace.flush()
+
+        records = longtrace.get_trace(root)
+        attrs = {r["message"]: r["attr"] for r in records if r["record_type"] in (0, 1)}
+        self.assertEqual(attrs["Outer"]["layer"], "outer")
+        self.assertEqual(attrs["Inner"]["request_id"], "r1")
+        self.assertEqual(attrs["Inner"]["layer"], "inner")
+        # 显式传入的 attr 优先
+        self.assertEqual(attrs["Inner"]["user"], 7)
+        self.assertEqual(attrs["Inner span"]["user"], 42)
+        self.assertEqual(attrs["Outer again"]["layer"], "outer")
+        self.assertNotIn("user", attrs["Outer again"])
+        self.assertNotIn("request_id", attrs["Outside"])
+
+    def test_46_current_span_id(self):
+        """测试 current_span_id 在 span 内返回当前 span，在 span 外返回 None"""
+        tracer = longtrace.Tracer()
+        self.assertIsNone(longtrace.current_span_id())
+        with tracer.span("Outer") as outer_id:
+            self.assertEqual(longtrace.current_span_id(), outer_id)
+            with tracer.span("Inner") as inner_id:
+                self.assertEqual(longtrace.current_span_id(), inner_id)
+            self.assertEqual(longtrace.current_span_id(), outer_id)
+            handle = tracer.begin_span("Manual")
+            self.assertEqual(longtrace.current_span_id(), handle.span_id)
+            handle.finish()
+        self.assertIsNone(longtrace.current_span_id())
+
+        # 其他线程没有打开的 span
+        seen = []
+        with tracer.span("Main thread"):
+            worker = threading.Thread(target=lambda: seen.append(longtrace.current_span_id()))
+            worker.start()
+            worker.join()
+        self.assertEqual(seen, [None])
+
+    def test_47_set_filter(self):
+        """测试 set_filter 丢弃消息匹配 glob 的 log 和 span（连同其子记录）"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        tracer.set_filter("health*")
+        tracer.log("healthcheck ok")
+        tracer.log("GET /health")
+        with tracer.span("health probe"):
+            tracer.log("Inside a dropped span")
+        with tracer.span("Request"):
+            tracer.log("heartbeat")
+
+        forked = tracer.fork()
+        forked.log("health from fork")
+        forked.log("Kept from fork")
+
+        tracer.set_filter(None)
+        tracer.log("health after clearing")
+        longtrace.flush()
+
+        messages = {r["message"] for r in longtrace.get_trace(root)}
+        self.assertEqual(messages, {"GET /health", "Request", "heartbeat", "Kept from fork", "health after clearing"})
+
+    def test_48_rate_limit(self):
+        """测试 set_rate_limit 限制单个 span 下每个时间窗口的记录数"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        tracer.set_rate_limit(50, window_seconds=10.0)
+        with tracer.span("Hot loop") as span_id:
+            for i in range(10000):
+                tracer.log(f"Iteration {i}")
+        longtrace.flush()
+
+        logs = [r for r in longtrace.get_trace(root) if r["parent_id"] == span_id]
+        self.assertLessEqual(len(logs), 50 * 10)
+        self.assertEqual(tracer.rate_limited_count(), 10000 - len(logs))
+
+        tracer.set_rate_limit(None)
+        with self.assertRaises(ValueError):
+            tracer.set_rate_limit(10, window_seconds=0)
+
+    def test_49_tracer_tags(self):
+        """测试 tags 出现在 Tracer 写入的每条记录中，且不覆盖用户设置的键"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root, tags={"env": "prod", "version": "1.2"})
+        self.assertIs(tracer.with_tag("region", "eu-west-1"), tracer)
+        tracer.log("Tagged log")
+        tracer.log("Overridden", attr=json.dumps({"env": "staging"}))
+        with tracer.span("Tagged span") as span:
+            pass
+        try:
+            with tracer.span("Failing span"):
+                raise KeyError("boom")
+        except KeyError:
+            pass
+        longtrace.flush()
+
+        records = longtrace.get_trace(root)
+        self.assertEqual(len(records), 7)
+        for record in records:
+            expected_env = "staging" if record["message"] == "Overridden" else "prod"
+            self.assertEqual(record["attr"]["env"], expected_env, record)
+            self.assertEqual(record["attr"]["version"], "1.2")
+            self.assertEqual(record["attr"]["region"], "eu-west-1")
+
+    def test_50_reset_context(self):
+        """测试 reset_context 清理未退出的 span，以及 log 在栈满时自动清理"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root, max_depth=3)
+
+        # 模拟 span 中途出错、__exit__ 从未被调用
+        leaked = tracer.span("Leaked")
+        leaked.__enter__()
+        self.assertEqual(tracer.stack_depth(), 1)
+        tracer.reset_context()
+        self.assertEqual(tracer.stack_depth(), 0)
+        self.assertIsNone(tracer.get_current_span_id())
+        with tracer.span("Clean") as clean_id:
+            self.assertEqual(tracer.stack_depth(), 1)
+
+        # 正常嵌套到 max_depth 时记录日志不会清空栈
+        with tracer.span("Outer"):
+            with tracer.span("Middle"):
+                with tracer.span("Inner") as inner_id:
+                    tracer.log("At max depth")
+                    self.assertEqual(tracer.stack_depth(), 3)
+                    self.assertEqual(tracer.get_current_span_id(), inner_id)
+        self.assertEqual(tracer.stack_depth(), 0)
+
+        for i in range(3):
+            tracer.span(f"Leaked {i}").__enter__()
+        with self.assertRaises(RuntimeError):
+            tracer.span("Too deep").__enter__()
+        tracer.log("After leak")
+        self.assertEqual(tracer.stack_depth(), 0)
+        longtrace.flush()
+
+        records = longtrace.get_trace(root)
+        clean = [r for r in records if r["span_id"] == clean_id and r["record_type"] == 1]
+        self.assertEqual(clean[0]["parent_id"], root)
+        warnings = [r for r in records if r["message"].startswith("Reset ")]
+        self.assertEqual(len(warnings), 1)
+        self.assertTrue(warnings[0]["message"].startswith("Reset 3 spans"))
+        self.assertEqual(warnings[0]["severity"], longtrace.WARN)
+        at_max = [r for r in records if r["message"] == "At max depth"]
+        self.assertEqual(at_max[0]["parent_id"], inner_id)
+        after = [r for r in records if r["message"] == "After leak"]
+        self.assertEqual(after[0]["parent_id"], root)
+
+    def test_51_span_duration(self):
+        """测试 span 结束记录带有 duration_ns"""
+        tracer = longtrace.Tracer()
+        with tracer.span("Timed") as span_id:
+            time.sleep(0.05)
+        longtrace.flush()
+
+        start, end = longtrace.query_span(span_id)
+        self.assertIsNone(start["duration_ns"])
+        self.assertGreaterEqual(end["duration_ns"], 50_000_000)
+        self.assertLess(end["duration_ns"], 5_000_000_000)
+
+    def test_52_get_slow_spans(self):
+        """测试 get_slow_spans 按耗时从长到短返回超过阈值的 span"""
+        tracer = longtrace.Tracer()
+        marker = str(uuid.uuid4())
+        for seconds in (0.25, 0.15, 0.2):
+            with tracer.span(f"Slow {seconds}", attr=json.dumps({"marker": marker})):
+                time.sleep(seconds)
+        longtrace.flush()
+
+        spans = [s for s in longtrace.get_slow_spans(140, limit=1000) if (s["attr"] or {}).get("marker") == marker]
+        self.assertEqual([s["message"] for s in spans], ["Slow 0.25", "Slow 0.2", "Slow 0.15"])
+        self.assertGreaterEqual(spans[0]["duration_ns"], 250_000_000)
+        self.assertEqual(set(spans[0]), {"span_id", "message", "duration_ns", "timestamp", "attr"})
+
+    def test_53_span_percentiles(self):
+        """测试 span_percentiles 按消息前缀统计耗时分位数"""
+        prefix = f"Percentiles {uuid.uuid4()}"
+        longtrace.report_batch([(prefix, str(uuid.uuid4()), str(uuid.uuid4()), None, 2)])
+        longtrace.flush()
+
+        # 没有耗时的 span end 不计入统计
+        self.assertEqual(longtrace.span_percentiles(prefix),
+                         {"count": 0, "p50_ns": 0, "p95_ns": 0, "p99_ns": 0, "max_ns": 0})
+
+        tracer = longtrace.Tracer()
+        for _ in range(5):
+            with tracer.span(f"{prefix} step"):
+                time.sleep(0.01)
+        longtrace.flush()
+
+        stats = longtrace.span_percentiles(prefix)
+        self.assertEqual(stats["count"], 5)
+        self.assertGreaterEqual(stats["p50_ns"], 10_000_000)
+        self.assertLessEqual(stats["p50_ns"], stats["p99_ns"])
+        self.assertLessEqual(stats["p99_ns"], stats["max_ns"])
+
+    def test_54_search(self):
+        """测试 search 的子串、正则和全文检索"""
+        marker = uuid.uuid4().hex
+        tracer = longtrace.Tracer()
+        tracer.log(f"Order {marker} shipped")
+        tracer.log(f"order {marker} cancelled")
+        longtrace.flush()
+
+        found = longtrace.search(f"ORDER {marker}")
+        self.assertEqual([r["message"] for r in found], [f"order {marker} cancelled", f"Order {marker} shipped"])
+        self.assertEqual(len(longtrace.search(f"ORDER {marker}", limit=1)), 1)
+
+        found = longtrace.search(f"^Order {marker} ship", regex=True)
+        self.assertEqual([r["message"] for r in found], [f"Order {marker} shipped"])
+        with self.assertRaises(Exception):
+            longtrace.search("(", regex=True)
+
+        found = longtrace.search_fulltext(f"ship w{marker}")
+        self.assertEqual(found, [])
+        tracer.log(f"Orders were shipping w{marker}")
+        longtrace.flush()
+        found = longtrace.search_fulltext(f"order ship w{marker}")
+        self.assertEqual([r["message"] for r in found], [f"Orders were shipping w{marker}"])
+
+    def test_55_span_elapsed(self):
+        """测试 SpanGuard 的 elapsed_ms 和 elapsed_ns"""
+        tracer = longtrace.Tracer()
+        guard = tracer.span("Elapsed")
+        with guard:
+            time.sleep(0.05)
+            self.assertGreaterEqual(guard.elapsed_ms, 50.0)
+            self.assertGreaterEqual(guard.elapsed_ns, 50_000_000)
+
+    def test_56_span_retries(self):
+        """测试 attempt 记录 RETRY 子记录，并在 span 结束时带上 retries"""
+        self.assertEqual(longtrace.RETRY, 7)
+        parent_id = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=parent_id)
+        guard = tracer.span("Flaky call", attr=json.dumps({"host": "a"}))
+        with guard:
+            for i in range(3):
+                self.assertEqual(guard.attempt(f"timeout {i}"), i + 1)
+        longtrace.flush()
+
+        records = [r for r in longtrace.get_trace(parent_id) if r["record_type"] in (longtrace.RETRY, 2)]
+        self.assertEqual([r["record_type"] for r in records], [7, 7, 7, 2])
+        self.assertEqual([r["attr"]["attempt"] for r in records[:3]], [1, 2, 3])
+        self.assertEqual(records[0]["attr"]["error"], "timeout 0")
+        self.assertTrue(all(r["parent_id"] == guard.span_id for r in records[:3]))
+        self.assertEqual((records[3]["attr"]["host"], records[3]["attr"]["retries"]), ("a", 3))
+
+        guard = tracer.span("Manual retries")
+        with guard:
+            guard.record_retry(5)
+        longtrace.flush()
+        end = [r for r in longtrace.query_span(guard.span_id) if r["record_type"] == 2]
+        self.assertEqual(end[0]["attr"]["retries"], 5)
+
+    def test_57_child_span(self):
+        """测试 child_span 在当前 span 下直接记录成对的开始和结束记录"""
+        parent_id = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=parent_id)
+        guard = tracer.span("Outer")
+        with guard:
+            guard.child_span("Lookup a")
+            guard.child_span("Lookup b", attr=json.dumps({"hit": True}))
+            # 不会压入 span 栈，之后的日志仍属于外层 span
+            self.assertEqual(longtrace.current_span_id(), guard.span_id)
+        longtrace.flush()
+
+        children = [r for r in longtrace.get_trace(parent_id) if r["parent_id"] == guard.span_id]
+        self.assertEqual([(r["message"], r["record_type"]) for r in children],
+                         [("Lookup a", 1), ("Lookup a", 2), ("Lookup b", 1), ("Lookup b", 2)])
+        self.assertNotEqual(children[0]["span_id"], children[2]["span_id"])
+        self.assertEqual(children[0]["span_id"], children[1]["span_id"])
+        self.assertTrue(children[3]["attr"]["hit"])
+        self.assertEqual(children[3]["duration_ns"], 0)
+
+    def test_58_fallback_to_stderr(self):
+        """测试未初始化的 registry 下 fallback_to_stderr 把记录写到 stderr"""
+        strict = longtrace.Tracer(registry="never-initialized")
+        with self.assertRaises(RuntimeError):
+            strict.log("Lost")
+
+        import tempfile
+        tracer = longtrace.Tracer(registry="never-initialized", fallback_to_stderr=True)
+        stderr = os.dup(2)
+        with tempfile.TemporaryFile() as captured:
+            os.dup2(captured.fileno(), 2)
+            try:
+                tracer.log("Library message", json.dumps({"k": 1}))
+                with tracer.span("Library span") as span:
+                    tracer.log("Inside")
+            finally:
+                os.dup2(stderr, 2)
+                os.close(stderr)
+            captured.seek(0)
+            lines = captured.read().decode().splitlines()
+
+        self.assertEqual(len(lines), 4)
+        self.assertTrue(all(line.startswith("[LONGTRACE] ") for line in lines))
+        self.assertTrue(lines[0].endswith(' Library message {"k": 1}'))
+        self.assertTrue(lines[2].endswith(" Inside"))
+        self.assertTrue(span)
+
+    def test_59_fanout_backend(self):
+        """测试 fanout:// 把同样的记录写入每个后端"""
+        import tempfile
+        directory = tempfile.mkdtemp()
+        db_path, file_path = os.path.join(directory, "trace.db"), os.path.join(directory, "trace.ndjson")
+        self.assertEqual(longtrace.initialize(f"fanout://sqlite://{db_path};file://{file_path}", name="fanout"),
+                         f"{db_path};{file_path}")
+        try:
+            parent_id = str(uuid.uuid4())
+            tracer = longtrace.Tracer(parent_id=parent_id, registry="fanout")
+            for i in range(10):
+                tracer.log(f"Fan-out {i}")
+            longtrace.flush(registry="fanout")
+
+            # 查询由第一个后端（SQLite）回答
+            self.assertEqual(len(longtrace.get_trace(parent_id, registry="fanout")), 10)
+            with open(file_path) as f:
+                self.assertEqual(sum(1 for line in f if json.loads(line)["parent_id"] == parent_id), 10)
+        finally:
+            longtrace.shutdown("fanout")
+
+    def test_60_export_json(self):
+        """测试 export_json 把记录导出为 JSON 数组"""
+        import tempfile
+        tracer = longtrace.Tracer()
+        with tracer.span("Export json") as span_id:
+            for i in range(500):
+                tracer.log(f"Row {i}", json.dumps({"i": i}))
+        longtrace.flush()
+
+        path = os.path.join(tempfile.mkdtemp(), "trace.json")
+        count = longtrace.export_json(path, start=time.time() - 60, record_type=0)
+        with open(path) as f:
+            rows = [r for r in json.load(f) if r["parent_id"] == span_id]
+        self.assertGreaterEqual(count, 500)
+        self.assertEqual(len(rows), 500)
+        self.assertEqual(rows[0]["attr"]["i"], 0)
+
+        self.assertEqual(longtrace.export_json(path, span_id=span_id), 2)
+        with open(path) as f:
+            self.assertEqual([r["record_type"] for r in json.load(f)], [1, 2])
+        with self.assertRaises(ValueError):
+            longtrace.export_json(path, span_id="not-a-uuid")
+
+    def test_61_import_json(self):
+        """测试 import_json 把 export_json 导出的记录写回数据库"""
+        import tempfile
+        directory = tempfile.mkdtemp()
+        db_path, json_path = os.path.join(directory, "trace.db"), os.path.join(directory, "trace.json")
+        tracer = longtrace.Tracer()
+        with tracer.span("Replay") as span_id:
+            tracer.log("Replayed", json.dumps({"k": 1}))
+        longtrace.flush()
+        self.assertEqual(longtrace.export_json(json_path, span_id=span_id), 2)
+
+        # 导入到另一个后端
+        longtrace.initialize(f"sqlite://{db_path}", name="import")
+        try:
+            self.assertEqual(longtrace.import_json(json_path, registry="import"), 2)
+            records = longtrace.query_span(span_id, registry="import")
+            self.assertEqual([r["record_type"] for r in records], [1, 2])
+            self.assertEqual(records, longtrace.query_span(span_id))
+        finally:
+            longtrace.shutdown("import")
+
+    def test_62_vacuum(self):
+        """测试 cleanup 之后 vacuum 回收空间"""
+        tracer = longtrace.Tracer()
+        for i in range(100):
+            tracer.log(f"Vacuum {i}")
+        longtrace.flush()
+        longtrace.vacuum()
+        longtrace.vacuum(analyze=False)
+
+    def test_63_get_children(self):
+        """测试 get_children 和 get_descendants"""
+        tracer = longtrace.Tracer()
+        with tracer.span("Root") as root:
+            for i in range(3):
+                with tracer.span(f"Child {i}"):
+                    tracer.log("Grandchild")
+        longtrace.flush()
+
+        # 每个子 span 有开始和结束两条记录
+        children = longtrace.get_children(root)
+        self.assertEqual([r["message"] for r in children], ["Child 0", "Child 0", "Child 1", "Child 1", "Child 2", "Child 2"])
+        self.assertEqual(len(longtrace.get_children(root, direct=False)), 9)
+        self.assertEqual(len(longtrace.get_descendants(root, limit=4)), 4)
+        with self.assertRaises(ValueError):
+            longtrace.get_children("not-a-uuid")
+
+    def test_64_timestamp_ns(self):
+        """测试记录的纳秒时间戳"""
+        tracer = longtrace.Tracer()
+        with tracer.span("Nanos") as span_id:
+            tracer.log("First")
+        longtrace.flush()
+        first, second = longtrace.query_span(span_id)
+        self.assertIsInstance(first["timestamp_ns"], int)
+        self.assertGreater(second["timestamp_ns"], first["timestamp_ns"])
+        timestamp = datetime.datetime.fromisoformat(first["timestamp"])
+        self.assertAlmostEqual(first["timestamp_ns"] / 1e9, timestamp.timestamp(), delta=1e-5)
+
+    def test_65_utc_timestamps(self):
+        """测试查询结果的时间戳是带 +00:00 的 UTC ISO 8601 字符串"""
+        tracer = longtrace.Tracer()
+        before = time.time()
+        with tracer.span("Utc") as span_id:
+            pass
+        longtrace.flush()
+        records = longtrace.query_span(span_id)
+        self.assertTrue(all(r["timestamp"].endswith("+00:00") for r in records))
+        # 查询范围使用 Unix 时间，与存储的 UTC 时间一致
+        timestamp = datetime.datetime.fromisoformat(records[0]["timestamp"]).timestamp()
+        self.assertAlmostEqual(timestamp, before, delta=5)
+        in_range = longtrace.query_range(before - 1, time.time() + 1, record_type=1, limit=100000)
+        self.assertIn(span_id, [r["span_id"] for r in in_range])
+
+    def test_66_uuid_strategy(self):
+        """测试 UUID 生成策略和 uuid_v7/uuid_v4"""
+        ids = [longtrace.uuid_v7() for _ in range(100)]
+        self.assertEqual(ids, sorted(ids))
+        self.assertEqual(uuid.UUID(ids[0]).version, 7)
+        self.assertEqual(uuid.UUID(longtrace.uuid_v4()).version, 4)
+
+        tracer = longtrace.Tracer().with_uuid_strategy("v4")
+        with tracer.span("Random") as span_id:
+            self.assertEqual(uuid.UUID(span_id).version, 4)
+        with self.assertRaises(ValueError):
+            tracer.with_uuid_strategy("v5")
+
+    def test_67_pool_size(self):
+        """测试 pool_max_size 等连接池参数"""
+        longtrace.initialize(CONN_STR, name="small_pool", pool_max_size=2, pool_min_idle=1, pool_connection_timeout_ms=5000, num_workers=3)
+        try:
+            parent_id = longtrace.uuid_v7()
+            tracer = longtrace.Tracer(parent_id=parent_id, registry="small_pool")
+            threads = [threading.Thread(target=lambda: [tracer.log("Pooled log") for _ in range(50)]) for _ in range(5)]
+            for t in threads:
+                t.start()
+            for t in threads:
+                t.join()
+            longtrace.flush("small_pool")
+            self.assertLessEqual(longtrace.pool_stats("small_pool")["connections"], 2)
+            self.assertEqual(len(longtrace.get_children(parent_id, registry="small_pool")), 250)
+        finally:
+            longtrace.shutdown("small_pool")
+        with self.assertRaises(RuntimeError):
+            longtrace.initialize(CONN_STR, name="bad_pool", pool_max_size=0)
+
+    def test_68_validate_connection_string(self):
+        """测试 validate_connection_string 的错误提示"""
+        longtrace.validate_connection_string(CONN_STR)
+        cases = [
+            ("user=postgres dbname=longtrace", "host=localhost"),
+            ("host=localhost dbname=longtrace", "user=postgres"),
+            ("host=localhost user=postgres port=70000", "1 to 65535"),
+        ]
+        for conn_str, suggestion in cases:
+            with self.assertRaises(ValueError) as ctx:
+                longtrace.validate_connection_string(conn_str)
+            self.assertIn(suggestion, str(ctx.exception))
+
+    def test_69_connect_retries(self):
+        """测试连接失败时的重试次数参数"""
+        with self.assertRaises(RuntimeError):
+            longtrace.initialize("host=longtrace.invalid user=postgres", name="unreachable", connect_retries=2, connect_retry_delay_ms=0)
+        self.assertFalse(longtrace.is_initialized("unreachable"))
+        with self.assertRaises(RuntimeError):
+            longtrace.initialize(CONN_STR, name="no_attempts", connect_retries=0)
+
+    def test_70_statement_timeout(self):
+        """测试 statement_timeout_ms 和 lock_timeout_ms 参数"""
+        longtrace.initialize(CONN_STR, name="timeouts", statement_timeout_ms=5000, lock_timeout_ms=2000)
+        try:
+            span_id = longtrace.uuid_v7()
+            tracer = longtrace.Tracer(parent_id=span_id, registry="timeouts")
+            tracer.log("Within the timeout")
+            longtrace.flush("timeouts")
+            self.assertEqual(len(longtrace.get_children(span_id, registry="timeouts")), 1)
+        finally:
+            longtrace.shutdown("timeouts")
+
+    def test_71_read_replica(self):
+        """测试 replica_connection_string 参数"""
+        longtrace.initialize(CONN_STR, name="replica", replica_connection_string=CONN_STR)
+        try:
+            span_id = longtrace.uuid_v7()
+            tracer = longtrace.Tracer(parent_id=span_id, registry="replica")
+            tracer.log("Read from the replica")
+            longtrace.flush("replica")
+            children = longtrace.get_children(span_id, registry="replica")
+            self.assertEqual([r["message"] for r in children], ["Read from the replica"])
+        finally:
+            longtrace.shutdown("replica")
+
+    def test_72_table_prefix(self):
+        """测试 table_prefix 参数, 不同前缀的记录互不可见"""
+        longtrace.initialize(CONN_STR, name="svc_a", table_prefix="svc_a")
+        longtrace.initialize(CONN_STR, name="svc_b", table_prefix="svc_b")
+        try:
+            parent_id = longtrace.uuid_v7()
+            for registry in ("svc_a", "svc_b"):
+                longtrace.Tracer(parent_id=parent_id, registry=registry).log(f"From {registry}")
+                longtrace.flush(registry)
+            for registry in ("svc_a", "svc_b"):
+                children = longtrace.get_children(parent_id, registry=registry)
+                self.assertEqual([r["message"] for r in children], [f"From {registry}"])
+        finally:
+            longtrace.shutdown("svc_a")
+            longtrace.shutdown("svc_b")
+        with self.assertRaises(RuntimeError):
+            longtrace.initialize(CONN_STR, name="bad_prefix", table_prefix="svc-a")
+
+    def test_73_pg_schema(self):
+        """测试 pg_schema 参数"""
+        longtrace.initialize(CONN_STR, name="pg_schema", pg_schema="tracing", table_prefix="pytest")
+        try:
+            parent_id = longtrace.uuid_v7()
+            longtrace.Tracer(parent_id=parent_id, registry="pg_schema").log("In the tracing schema")
+            longtrace.flush("pg_schema")
+            children = longtrace.get_children(parent_id, registry="pg_schema")
+            self.assertEqual([r["message"] for r in children], ["In the tracing schema"])
+            self.assertEqual(longtrace.get_children(parent_id), [])
+        finally:
+            longtrace.shutdown("pg_schema")
+
+    def test_74_scrubbing(self):
+        """测试 scrub_keys 和 scrub_patterns 参数"""
+        longtrace.initialize(CONN_STR, name="scrubbed", scrub_keys=["password", "token"], scrub_patterns=[r"\d{16}"])
+        try:
+            parent_id = longtrace.uuid_v7()
+            tracer = longtrace.Tracer(parent_id=parent_id, registry="scrubbed")
+            tracer.log("Paid with 4111111111111111", attr=json.dumps({"password": "secret", "user": "alice"}))
+            longtrace.flush("scrubbed")
+            [record] = longtrace.get_children(parent_id, registry="scrubbed")
+            self.assertEqual(record["message"], "Paid with [REDACTED]")
+            self.assertEqual(record["attr"]["password"], "[REDACTED]")
+            self.assertEqual(record["attr"]["user"], "alice")
+        finally:
+            longtrace.shutdown("scrubbed")
+        with self.assertRaises(ValueError):
+            longtrace.initialize(CONN_STR, name="bad_scrub", scrub_patterns=["("])
+
+    def test_75_encrypted_attr_keys(self):
+        """测试 encrypted_attr_keys 加密和 decrypt_attr 解密"""
+        key = bytes(range(32))
+        longtrace.initialize(CONN_STR, name="encrypted", encrypted_attr_keys=["ssn"], encryption_key=key)
+        try:
+            parent_id = longtrace.uuid_v7()
+            tracer = longtrace.Tracer(parent_id=parent_id, registry="encrypted")
+            tracer.log("Visit", attr=json.dumps({"ssn": "078-05-1120", "patient": 7}))
+            longtrace.flush("encrypted")
+            [record] = longtrace.get_children(parent_id, registry="encrypted")
+            self.assertIn("_enc", record["attr"]["ssn"])
+            self.assertEqual(record["attr"]["patient"], 7)
+            decrypted = json.loads(longtrace.decrypt_attr(json.dumps(record["attr"]), registry="encrypted"))
+            self.assertEqual(decrypted["ssn"], "078-05-1120")
+            self.assertEqual(decrypted["patient"], 7)
+        finally:
+            longtrace.shutdown("encrypted")
+        with self.assertRaises(ValueError):
+            longtrace.initialize(CONN_STR, name="bad_key", encrypted_attr_keys=["ssn"], encryption_key=b"short")
+
+    def test_76_record_signing(self):
+        """测试 hmac_secret 签名和 verify_record 校验"""
+        longtrace.initialize(CONN_STR, name="signed", hmac_secret=bytes(range(32)))
+        try:
+            parent_id = longtrace.uuid_v7()
+            tracer = longtrace.Tracer(parent_id=parent_id, registry="signed")
+            tracer.log("Approved", attr=json.dumps({"amount": 10}))
+            longtrace.flush("signed")
+            [record] = longtrace.get_children(parent_id, registry="signed")
+            self.assertEqual(len(record["signature"]), 64)
+            self.assertTrue(longtrace.verify_record(record, registry="signed"))
+            self.assertFalse(longtrace.verify_record(dict(record, message="Rejected"), registry="signed"))
+            self.assertFalse(longtrace.verify_record(dict(record, attr={"amount": 1000}), registry="signed"))
+        finally:
+            longtrace.shutdown("signed")
+        with self.assertRaises(ValueError):
+            longtrace.initialize(CONN_STR, name="bad_secret", hmac_secret=b"short")
+
+    def test_77_size_limits(self):
+        """测试 max_attr_bytes 和 max_message_bytes 截断"""
+        longtrace.initialize(CONN_STR, name="limited", max_attr_bytes=64, max_message_bytes=8, capture_metadata=False)
+        try:
+            parent_id = longtrace.uuid_v7()
+            tracer = longtrace.Tracer(parent_id=parent_id, registry="limited")
+            tracer.log("A very long message", attr=json.dumps({"blob": "x" * 1000}))
+            longtrace.flush("limited")
+            [record] = longtrace.get_children(parent_id, registry="limited")
+            self.assertEqual(record["message"], "A very l...[truncated]")
+            self.assertTrue(record["attr"].endswith("...[truncated]"))
+            [notice] = longtrace.get_children(record["span_id"], registry="limited")
+            self.assertEqual(notice["record_type"], longtrace.TRUNCATION_NOTICE)
+            self.assertEqual(notice["attr"]["message_bytes"], 19)
+        finally:
+            longtrace.shutdown("limited")
+
+    def test_78_flush_releases_gil(self):
+        """测试 flush_sync() 等待写入时不阻塞其他 Python 线程"""
+        longtrace.initialize(CONN_STR, name="gil", batch_size=100_000, flush_interval_ms=600_000)
+        try:
+            parent_id = longtrace.uuid_v7()
+            longtrace.report_batch([(f"log {i}", longtrace.uuid_v7(), parent_id, None, 0) for i in range(20_000)], registry="gil")
+            ticks = [0]
+            stop = threading.Event()
+
+            def spin():
+                while not stop.is_set():
+                    ticks[0] += 1
+
+            spinner = threading.Thread(target=spin)
+            spinner.start()
+            try:
+                before = ticks[0]
+                longtrace.flush_sync(30.0, registry="gil")
+                after = ticks[0]
+            finally:
+                stop.set()
+                spinner.join()
+            # With the GIL held for the whole flush, the spinner could not have counted
+            self.assertGreater(after, before)
+        finally:
+            longtrace.shutdown("gil")
+
+    @unittest.skipUnless(sys.platform.startswith("linux"), "需要 fork")
+    def test_79_post_fork(self):
+        """测试 post_fork() 让 fork 出的子进程重新打开数据库并写入"""
+        import multiprocessing
+        longtrace.initialize(CONN_STR, name="forked")
+        try:
+            parent_id = longtrace.uuid_v7()
+            with multiprocessing.get_context("fork").Pool(2, initializer=longtrace.post_fork) as pool:
+                pids = set(pool.map(log_in_child, [parent_id] * 4))
+            self.assertNotIn(os.getpid(), pids)
+            messages = {record["message"] for record in longtrace.get_children(parent_id, registry="forked")}
+            self.assertEqual(messages, {f"child {pid}" for pid in pids})
+
+            # The parent's own pool and writer keep working
+            longtrace.Tracer(parent_id=parent_id, registry="forked").log("parent")
+            longtrace.flush("forked")
+            records = longtrace.get_children(parent_id, registry="forked")
+            self.assertEqual(len(records), 5)
+            self.assertIn("parent", {record["message"] for record in records})
+        finally:
+            longtrace.shutdown("forked")
+
+    @unittest.skipUnless(sys.platform.startswith("linux"), "需要 POSIX 信号")
+    def test_80_signal_handlers(self):
+        """测试 register_signal_handlers() 在 SIGTERM 时写入所有待写记录"""
+        import signal
+        import subprocess
+        parent_id = longtrace.uuid_v7()
+        script = f"""
+import sys, time, longtrace
+longtrace.initialize({CONN_STR!r}, candidate_name="longtrace", batch_size=100_000, flush_interval_ms=600_000)
+longtrace.register_signal_handlers()
+tracer = longtrace.Tracer(parent_id={parent_id!r})
+for i in range(500):
+    tracer.log(f"in flight {{i}}")
+print("ready", flush=True)
+time.sleep(60)
+"""
+        child = subprocess.Popen([sys.executable, "-c", script], stdout=subprocess.PIPE, stderr=subprocess.PIPE, text=True)
+        try:
+            self.assertEqual(child.stdout.readline().strip(), "ready")
+            child.send_signal(signal.SIGTERM)
+            child.communicate(timeout=30)
+        finally:
+            child.kill()
+        # Still killed by the signal, so atexit did not run and the handler wrote them
+        self.assertEqual(child.returncode, -signal.SIGTERM)
+        self.assertEqual(len(longtrace.get_children(parent_id, limit=1000)), 500)
+
+    def test_81_export_jaeger(self):
+        """测试 export_jaeger 导出 Jaeger JSON 格式"""
+        import tempfile
+        tracer = longtrace.Tracer()
+        start = time.time() - 1
+        with tracer.span("Jaeger root", json.dumps({"route": "/checkout"})) as root_id:
+            for i in range(4):
+                with tracer.span(f"Jaeger step {i}"):
+                    tracer.log("working", json.dumps({"i": i}))
+        longtrace.flush()
+
+        path = os.path.join(tempfile.mkdtemp(), "jaeger.json")
+        self.assertGreaterEqual(longtrace.export_jaeger(path, "checkout", start=start), 5)
+        with open(path) as f:
+            data = json.load(f)["data"]
+        [trace] = [t for t in data if t["traceID"] == root_id.replace("-", "")]
+        self.assertEqual(trace["processes"]["p1"]["serviceName"], "checkout")
+        spans = trace["spans"]
+        self.assertEqual(len(spans), 5)
+        for span in spans:
+            self.assertEqual(len(span["spanID"]), 16)
+            self.assertIsInstance(span["startTime"], int)
+            self.assertIsInstance(span["duration"], int)
+            self.assertIn(span["processID"], trace["processes"])
+        self.assertEqual(spans[0]["operationName"], "Jaeger root")
+        self.assertIn({"key": "route", "type": "string", "value": "/checkout"}, spans[0]["tags"])
+        self.assertEqual([r["refType"] for r in spans[1]["references"]], ["CHILD_OF"])
+        self.assertEqual(spans[1]["logs"][0]["fields"][0], {"key": "event", "type": "string", "value": "working"})
+
+    def test_82_export_zipkin(self):
+        """测试 export_zipkin 导出 Zipkin v2 JSON 格式"""
+        import tempfile
+        tracer = longtrace.Tracer()
+        start = time.time() - 1
+        with tracer.span("Zipkin root", json.dumps({"route": "/checkout", "attempt": 2})) as root_id:
+            with tracer.span("Zipkin child") as child_id:
+                with tracer.span("Zipkin grandchild") as grandchild_id:
+                    tracer.log("connecting", "{}")
+        longtrace.flush()
+
+        path = os.path.join(tempfile.mkdtemp(), "zipkin.json")
+        self.assertGreaterEqual(longtrace.export_zipkin(path, "checkout", start=start), 3)
+        with open(path) as f:
+            spans = [s for s in json.load(f) if s["traceId"] == root_id.replace("-", "")]
+        self.assertEqual([s["name"] for s in spans], ["Zipkin root", "Zipkin child", "Zipkin grandchild"])
+        ids = [s["id"] for s in spans]
+        self.assertEqual(ids, [i.replace("-", "")[16:] for i in (root_id, child_id, grandchild_id)])
+        self.assertNotIn("parentId", spans[0])
+        self.assertEqual(spans[1]["parentId"], ids[0])
+        self.assertEqual(spans[2]["parentId"], ids[1])
+        for span in spans:
+            self.assertIsInstance(span["timestamp"], int)
+            self.assertIsInstance(span["duration"], int)
+            self.assertEqual(span["localEndpoint"], {"serviceName": "checkout"})
+        self.assertEqual(spans[0]["tags"]["route"], "/checkout")
+        self.assertEqual(spans[0]["tags"]["attempt"], "2")
+        self.assertEqual(spans[2]["annotations"][0]["value"], "connecting")
+
+    @unittest.skipUnless(hasattr(longtrace, "start_metrics_server"), "requires the prometheus feature")
+    def test_83_metrics_server(self):
+        """测试 start_metrics_server 提供 Prometheus 指标"""
+        import urllib.error
+        import urllib.request
+        tracer = longtrace.Tracer()
+        for i in range(5):
+            tracer.log(f"Prometheus log {i}", "{}")
+        longtrace.flush()
+
+        port = longtrace.start_metrics_server(0)
+        with urllib.request.urlopen(f"http://127.0.0.1:{port}/metrics") as response:
+            self.assertTrue(response.headers["Content-Type"].startswith("text/plain"))
+            body = response.read().decode()
+        samples = {}
+        for line in body.splitlines():
+            if line and not line.startswith("#"):
+                name, value = line.rsplit(" ", 1)
+                samples[name] = float(value)
+        for name in ("records_inserted_total", "records_failed_total", "batch_flush_duration_seconds_count", "pending_records"):
+            self.assertIn(name, samples)
+            self.assertGreaterEqual(samples[name], 0)
+        self.assertGreaterEqual(samples["records_inserted_total"], 5)
+        with self.assertRaises(urllib.error.HTTPError):
+            urllib.request.urlopen(f"http://127.0.0.1:{port}/other")
+
+    def test_84_statsd(self):
+        """测试 statsd_host 在每次写入后发送 StatsD 指标"""
+        import socket
+        server = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
+        server.bind(("127.0.0.1", 0))
+        server.settimeout(5)
+        port = server.getsockname()[1]
+        longtrace.initialize(CONN_STR, name="statsd", statsd_host="127.0.0.1", statsd_port=port, statsd_prefix="svc")
+        try:
+            tracer = longtrace.Tracer(registry="statsd")
+            for i in range(4):
+                tracer.log(f"StatsD log {i}", "{}")
+            longtrace.flush(registry="statsd")
+            lines = [server.recv(512).decode() for _ in range(3)]
+        finally:
+            server.close()
+            longtrace.shutdown("statsd")
+        self.assertEqual(lines[0], "svc.pending_records:0|g")
+        self.assertEqual(lines[1], "svc.records_inserted:4|c")
+        self.assertRegex(lines[2], r"^svc\.flush_duration_ms:\d+\|ms$")
+
+    def test_85_wal_path(self):
+        """测试 wal_path 在写入成功后清空预写日志"""
+        import tempfile
+        with tempfile.TemporaryDirectory() as tmp:
+            wal_path = os.path.join(tmp, "longtrace.wal")
+            longtrace.initialize(CONN_STR, name="wal", wal_path=wal_path)
+            try:
+                tracer = longtrace.Tracer(registry="wal")
+                for i in range(3):
+                    tracer.log(f"WAL log {i}", "{}")
+                longtrace.flush(registry="wal")
+                self.assertTrue(os.path.exists(wal_path))
+                self.assertEqual(os.path.getsize(wal_path), 0)
+            finally:
+                longtrace.shutdown("wal")
+
+    def test_86_buffered_postgres(self):
+        """测试 buffered+postgres:// 在 PostgreSQL 不可达时先写入本地 SQLite"""
+        import sqlite3
+        import tempfile
+        with tempfile.TemporaryDirectory() as tmp:
+            local_db = os.path.join(tmp, "buffer.db")
+            with self.assertRaises(Exception):
+                longtrace.initialize("buffered+postgresql://postgres@127.0.0.1:1/traces", name="buffered")
+            longtrace.initialize("buffered+postgresql://postgres@127.0.0.1:1/traces", name="buffered", local_db=local_db, forward_interval_ms=60_000)
+            try:
+                tracer = longtrace.Tracer(registry="buffered")
+                for i in range(3):
+                    tracer.log(f"Buffered log {i}", "{}")
+                longtrace.flush(registry="buffered")
+            finally:
+                longtrace.shutdown("buffered")
+            conn = sqlite3.connect(local_db)
+            try:
+                rows = conn.execute("SELECT message, forwarded FROM records ORDER BY id").fetchall()
+            finally:
+                conn.close()
+            self.assertEqual(rows, [(f"Buffered log {i}", 0) for i in range(3)])
+
+    def test_87_configure(self):
+        """测试 configure() 在运行时修改 batch_size 和 sample_rate"""
+        longtrace.initialize(CONN_STR, name="configured", batch_size=1024)
+        try:
+            longtrace.configure(batch_size=5, registry="configured")
+            tracer = longtrace.Tracer(registry="configured")
+            for i in range(5):
+                tracer.log(f"Configured log {i}", "{}")
+            # 第 5 条记录填满新的批次，不需要手动 flush
+            stats = longtrace.writer_stats(registry="configured")
+            self.assertEqual(stats["pending"], 0)
+            self.assertEqual(stats["total_flushed"], 5)
+
+            with self.assertRaises(RuntimeError) as cm:
+                longtrace.configure(pool_max_size=4, registry="configured")
+            self.assertIn("pool_max_size", str(cm.exception))
+            with self.assertRaises(RuntimeError):
+                longtrace.configure(sample_rate=2.0, registry="configured")
+
+            longtrace.configure(sample_rate=0.0, registry="configured")
+            tracer.log("Never recorded", "{}")
+            longtrace.flush(registry="configured")
+            self.assertEqual(longtrace.writer_stats(registry="configured")["total_flushed"], 5)
+        finally:
+            longtrace.shutdown("configured")
+
+    def test_88_error_priority(self):
+        """测试 tracer.error() 不等批次填满就立即写入"""
+        longtrace.initialize(CONN_STR, name="priority", batch_size=1024)
+        try:
+            tracer = longtrace.Tracer(registry="priority")
+            for i in range(3):
+                tracer.info(f"Batched log {i}")
+            tracer.error("Priority error")
+            stats = longtrace.writer_stats(registry="priority")
+            self.assertEqual(stats["pending"], 3)
+            self.assertEqual(stats["total_flushed"], 1)
+        finally:
+            longtrace.shutdown("priority")
+
+    def test_89_auto_tune(self):
+        """测试 auto_tune 选项下批次仍按当前大小写入"""
+        longtrace.initialize(CONN_STR, name="tuned", batch_size=16, auto_tune=True)
+        try:
+            tracer = longtrace.Tracer(registry="tuned")
+            for i in range(16):
+                tracer.log(f"Tuned log {i}", "{}")
+            stats = longtrace.writer_stats(registry="tuned")
+            self.assertEqual(stats["pending"], 0)
+            self.assertEqual(stats["total_flushed"], 16)
+        finally:
+            longtrace.shutdown("tuned")
+
+    def test_90_async_with_span(self):
+        """测试 Tracer.span() 支持 async with，并在并发任务之间隔离"""
+        root = str(uuid.uuid4())
+        tracer = longtrace.Tracer(parent_id=root)
+        seen = {}
+
+        async def task(name):
+            async with tracer.span(name) as span_id:
+                await asyncio.sleep(0.01)
+                tracer.log(name + " log")
+                seen[name] = (span_id, tracer.get_current_span_id())
+
+        async def failing():
+            async with tracer.span("Failing"):
+                raise ValueError("boom")
+
+        async def main():
+            await asyncio.gather(task("A"), task("B"))
+            with self.assertRaises(ValueError):
+                await failing()
+
+        asyncio.run(main())
+        self.assertIsNone(tracer.get_current_span_id())
+        longtrace.flush()
+
+        records = longtrace.get_trace(root)
+        parents = {(r["record_type"], r["message"]): r["parent_id"] for r in records}
+        for name, (span_id, current) in seen.items():
+            self.assertEqual(current, span_id)
+            self.assertEqual(parents[(1, name)], root)
+            self.assertEqual(parents[(0, name + " log")], span_id)
+        ends = {r["message"]: r["status"] for r in records if r["record_type"] == 2}
+        self.assertEqual(ends["Failing"], longtrace.STATUS_ERROR)
+
 if __name__ == "__main__":
     unittest.main()
\ No newline at end of file